@@ -0,0 +1,105 @@
+use crate::access_log::{AccessLogRecord, RotatingFile};
+use crate::config::AccessLogConfig;
+use crate::metrics::metrics;
+use crate::{Result, NodeBalancerError};
+use once_cell::sync::OnceCell;
+use rand::Rng;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use tracing::error;
+
+static ACCESS_LOG: OnceCell<AccessLog> = OnceCell::new();
+
+// Records waiting to be written. Connections never wait on the log, records that don't fit are dropped.
+const QUEUE_SIZE: usize = 16 * 1024;
+
+pub struct AccessLog {
+    config: AccessLogConfig,
+    records: SyncSender<String>,
+}
+
+enum Output {
+    Stdout(io::Stdout),
+    File(RotatingFile),
+}
+
+impl AccessLog {
+    /// Starts writing the access log on its own thread if it is enabled
+    pub fn init(config: &AccessLogConfig) -> Result<()> {
+        if !config.enabled || ACCESS_LOG.get().is_some() {
+            return Ok(());
+        }
+
+        let output = match &config.path {
+            Some(path) => Output::File(RotatingFile::open(path, config.max_size, config.max_files)
+                .map_err(|e| NodeBalancerError::AccessLogError(path.clone(), e))?),
+            None => Output::Stdout(io::stdout()),
+        };
+
+        let (tx, rx) = mpsc::sync_channel(QUEUE_SIZE);
+        std::thread::Builder::new()
+            .name("access-log".to_owned())
+            .spawn(move || write_records(rx, output))
+            .map_err(NodeBalancerError::IOError)?;
+
+        let _ = ACCESS_LOG.set(AccessLog {
+            config: config.clone(),
+            records: tx,
+        });
+
+        Ok(())
+    }
+
+    /// Whether a connection should be logged, applying the sample rate
+    pub fn sampled(&self, failed: bool) -> bool {
+        (failed && self.config.always_log_errors) || rand::thread_rng().gen::<f64>() < self.config.sample_rate
+    }
+
+    pub fn write(&self, record: &AccessLogRecord) {
+        let line = match serde_json::to_string(record) {
+            Ok(v) => v,
+            Err(e) => return error!("Failed to serialise access log record: {}", e),
+        };
+
+        // Full or the writer thread is gone
+        if self.records.try_send(line).is_err() {
+            metrics().access_log_dropped.inc();
+        }
+    }
+}
+
+/// The access log, if it is enabled
+pub fn access_log() -> Option<&'static AccessLog> {
+    ACCESS_LOG.get()
+}
+
+// Flushes whenever the queue runs dry, so records show up promptly without a write per line when busy
+fn write_records(records: Receiver<String>, mut output: Output) {
+    while let Ok(line) = records.recv() {
+        let mut res = output.write_line(&line);
+
+        while let Ok(line) = records.try_recv() {
+            res = res.and_then(|_| output.write_line(&line));
+        }
+
+        if let Err(e) = res.and_then(|_| output.flush()) {
+            error!("Failed to write access log: {}", e);
+        }
+    }
+}
+
+impl Output {
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => writeln!(stdout.lock(), "{}", line),
+            Output::File(file) => file.write_line(line),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::File(file) => file.flush(),
+        }
+    }
+}
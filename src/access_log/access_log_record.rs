@@ -0,0 +1,24 @@
+use serde::Serialize;
+use std::net::SocketAddr;
+
+/// One line of the access log, written when a proxied connection ends
+#[derive(Debug, Serialize)]
+pub struct AccessLogRecord<'a> {
+    // When the connection ended, RFC 3339 in UTC
+    pub time: String,
+    pub client_addr: SocketAddr,
+    pub listen_port: u16,
+    // Unset if the connection ended before a destination was picked
+    pub pod: Option<&'a str>,
+    pub node: Option<&'a str>,
+    // The node address that answered, unset if connecting failed
+    pub node_addr: Option<SocketAddr>,
+    pub connect_ms: Option<f64>,
+    pub bytes_from_client: u64,
+    pub bytes_to_client: u64,
+    // From accept to close
+    pub duration_ms: f64,
+    pub reason: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
@@ -0,0 +1,9 @@
+#[allow(clippy::module_inception)]
+mod access_log;
+pub use access_log::{AccessLog, access_log};
+
+mod access_log_record;
+pub use access_log_record::AccessLogRecord;
+
+mod rotating_file;
+pub use rotating_file::RotatingFile;
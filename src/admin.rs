@@ -0,0 +1,120 @@
+use crate::{Result, NodeBalancerError};
+use crate::config::AdminConfig;
+use crate::logging::LogFilter;
+use crate::metrics::metrics;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{error, info};
+
+/// Whether the balancer should be sent traffic, reported on /ready
+pub struct Readiness {
+    ready: AtomicBool,
+}
+
+impl Readiness {
+    pub fn new() -> Readiness {
+        Readiness {
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Readiness {
+        Readiness::new()
+    }
+}
+
+/// Serves the admin endpoints until the process exits
+pub async fn serve(config: &AdminConfig, readiness: Arc<Readiness>, log_filter: Arc<LogFilter>) -> Result<()> {
+    let addr: SocketAddr = config.listen_addr.parse()
+        .map_err(|_| NodeBalancerError::AdminServerError(format!("invalid listen address {}", config.listen_addr)))?;
+    let log_filter_writable = config.log_filter_writable;
+
+    let make_service = make_service_fn(move |_| {
+        let readiness = Arc::clone(&readiness);
+        let log_filter = Arc::clone(&log_filter);
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let readiness = Arc::clone(&readiness);
+                let log_filter = Arc::clone(&log_filter);
+                async move { Ok::<_, Infallible>(handle(req, &readiness, &log_filter, log_filter_writable).await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)
+        .map_err(|e| NodeBalancerError::AdminServerError(e.to_string()))?
+        .serve(make_service);
+
+    info!("Admin server listening on {}", addr);
+
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("Admin server failed: {}", e);
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle(req: Request<Body>, readiness: &Readiness, log_filter: &LogFilter, log_filter_writable: bool) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/healthz") => Response::new(Body::from("ok")),
+
+        (&Method::GET, "/ready") if readiness.is_ready() => Response::new(Body::from("ready")),
+        (&Method::GET, "/ready") => Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(Body::from("not ready"))
+            .unwrap(),
+
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(Body::from(metrics().encode()))
+            .unwrap(),
+
+        (&Method::GET, "/log_filter") => Response::new(Body::from(log_filter.directives())),
+        // Takes directives in RUST_LOG syntax as the body, until the next restart or change to logging.filter
+        (&Method::PUT, "/log_filter") if log_filter_writable => set_log_filter(req, log_filter).await,
+        (&Method::PUT, "/log_filter") => Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Body::from("admin.log_filter_writable is not set"))
+            .unwrap(),
+
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
+
+async fn set_log_filter(req: Request<Body>, log_filter: &LogFilter) -> Response<Body> {
+    let res = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => match std::str::from_utf8(&body) {
+            Ok(directives) => log_filter.set(directives.trim()),
+            Err(_) => Err(NodeBalancerError::LoggingError("filter is not valid UTF-8".to_owned())),
+        },
+        Err(e) => Err(NodeBalancerError::LoggingError(e.to_string())),
+    };
+
+    match res {
+        Ok(()) => Response::new(Body::from(log_filter.directives())),
+        Err(e) => Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(e.to_string()))
+            .unwrap(),
+    }
+}
@@ -0,0 +1,158 @@
+use rand::Rng;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::warn;
+use serde::Deserialize;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackoffPolicy {
+    #[serde(with = "humantime_serde")]
+    pub initial: Duration,
+    #[serde(with = "humantime_serde")]
+    pub max: Duration,
+    pub multiplier: f64,
+    // Fraction of each delay that is randomised, 0.0 to 1.0
+    pub jitter: f64,
+    // An attempt that stayed healthy for this long resets the delay back to initial
+    #[serde(with = "humantime_serde")]
+    pub reset_after: Duration,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> BackoffPolicy {
+        BackoffPolicy {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.5,
+            reset_after: Duration::from_secs(120),
+        }
+    }
+}
+
+pub struct Backoff {
+    policy: BackoffPolicy,
+    attempt: u32,
+    started: Instant,
+}
+
+impl Backoff {
+    pub fn new(policy: BackoffPolicy) -> Backoff {
+        Backoff {
+            policy,
+            attempt: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Marks the start of an attempt, used to decide whether a later failure follows a healthy period
+    pub fn start(&mut self) {
+        self.started = Instant::now();
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        if self.started.elapsed() >= self.policy.reset_after {
+            self.reset();
+        }
+
+        let exp = self.policy.multiplier.powi(self.attempt.min(64) as i32);
+        let base = self.policy.initial.as_secs_f64() * exp;
+        let capped = base.min(self.policy.max.as_secs_f64());
+
+        let jitter = self.policy.jitter.clamp(0.0, 1.0);
+        let delay = capped * (1.0 - jitter * rand::thread_rng().gen::<f64>());
+
+        self.attempt = self.attempt.saturating_add(1);
+        Duration::from_secs_f64(delay)
+    }
+}
+
+/// Runs `f` until it succeeds, sleeping between failed attempts according to `policy`
+pub async fn retry<T, E, F, Fut>(policy: &BackoffPolicy, what: &str, mut f: F) -> T
+where
+    E: std::fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::result::Result<T, E>>,
+{
+    let mut backoff = Backoff::new(policy.clone());
+
+    loop {
+        backoff.start();
+
+        match f().await {
+            Ok(v) => return v,
+            Err(e) => {
+                let delay = backoff.next_delay();
+                warn!("{} failed: {}, retrying in {:?}", what, e, delay);
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> BackoffPolicy {
+        BackoffPolicy {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.0,
+            reset_after: Duration::from_secs(120),
+        }
+    }
+
+    #[test]
+    fn grows_by_multiplier_up_to_max() {
+        let mut backoff = Backoff::new(policy());
+        let delays: Vec<Duration> = (0..6).map(|_| backoff.next_delay()).collect();
+
+        assert_eq!(delays, vec![
+            Duration::from_millis(100),
+            Duration::from_millis(200),
+            Duration::from_millis(400),
+            Duration::from_millis(800),
+            Duration::from_secs(1),
+            Duration::from_secs(1),
+        ]);
+    }
+
+    #[test]
+    fn jitter_only_shortens_delays() {
+        let mut backoff = Backoff::new(BackoffPolicy { jitter: 0.5, ..policy() });
+
+        for _ in 0..100 {
+            let delay = backoff.next_delay();
+            assert!(delay <= Duration::from_secs(1));
+        }
+
+        backoff.reset();
+        let delay = backoff.next_delay();
+        assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100), "{:?}", delay);
+    }
+
+    #[test]
+    fn resets_after_a_healthy_period() {
+        let mut backoff = Backoff::new(BackoffPolicy { reset_after: Duration::ZERO, ..policy() });
+        backoff.next_delay();
+        backoff.next_delay();
+
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn many_attempts_stay_at_max() {
+        let mut backoff = Backoff::new(BackoffPolicy { multiplier: 10.0, ..policy() });
+
+        for _ in 0..1000 {
+            assert!(backoff.next_delay() <= Duration::from_secs(1));
+        }
+    }
+}
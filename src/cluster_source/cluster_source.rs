@@ -0,0 +1,43 @@
+use crate::Result;
+use async_trait::async_trait;
+use futures_util::stream::BoxStream;
+use k8s_openapi::api::core::v1::{Node, Pod, Service};
+use kube::Client;
+use kube_runtime::watcher::Event;
+use std::collections::BTreeMap;
+
+/// Changes to one kind of object, starting with a Restarted event listing every object
+pub type WatchStream<K> = BoxStream<'static, Result<Event<K>>>;
+
+/// Where the router learns about nodes, services and pods. Lists seed the router and watches keep it current.
+/// A watch that ends or fails is restarted with backoff.
+#[async_trait]
+pub trait ClusterSource: Send + Sync {
+    async fn list_nodes(&self) -> Result<Vec<Node>>;
+
+    fn watch_nodes(&self) -> WatchStream<Node>;
+
+    async fn get_service(&self, namespace: &str, name: &str) -> Result<Service>;
+
+    /// Services in every namespace, the router picks out the one it balances
+    fn watch_services(&self) -> WatchStream<Service>;
+
+    /// Pods in `namespace` whose labels match all of `selector`
+    async fn list_pods(&self, namespace: &str, selector: &BTreeMap<String, String>) -> Result<Vec<Pod>>;
+
+    /// Pods in every namespace, the router works out which back the service, so a change to the service's
+    /// namespace doesn't need a new watch
+    fn watch_pods(&self) -> WatchStream<Pod>;
+
+    /// The client for the cluster behind this source, if there is one, for the balancer's duties beyond
+    /// routing such as emitting events
+    fn kube_client(&self) -> Option<Client> {
+        None
+    }
+
+    /// Whether a failed list may succeed if tried again. Seeding retries with backoff when it may, and fails
+    /// straight away when it can't.
+    fn retries_lists(&self) -> bool {
+        true
+    }
+}
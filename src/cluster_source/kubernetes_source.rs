@@ -0,0 +1,82 @@
+use crate::{Result, NodeBalancerError};
+use crate::cluster_source::{ClusterSource, WatchStream};
+use async_trait::async_trait;
+use futures_util::{StreamExt, TryStreamExt};
+use k8s_openapi::api::core::v1::{Node, Pod, Service};
+use kube::{Api, Client, Config as KubeConfig};
+use kube::api::ListParams;
+use kube_runtime::watcher;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+/// Reads the cluster through the Kubernetes API
+pub struct KubernetesSource {
+    client: Client,
+}
+
+impl KubernetesSource {
+    pub fn new(client: Client) -> KubernetesSource {
+        KubernetesSource {
+            client,
+        }
+    }
+
+    /// Connects with the in-cluster config, or the local kubeconfig outside a cluster
+    pub async fn infer() -> Result<KubernetesSource> {
+        let kube_config = KubeConfig::infer().await.map_err(NodeBalancerError::KubeConfigError)?;
+        let client = Client::try_from(kube_config).map_err(NodeBalancerError::KubeConfigError)?;
+
+        Ok(KubernetesSource::new(client))
+    }
+
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    fn build_selector(selector: &BTreeMap<String, String>) -> String {
+        selector.iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+}
+
+#[async_trait]
+impl ClusterSource for KubernetesSource {
+    async fn list_nodes(&self) -> Result<Vec<Node>> {
+        let node_api: Api<Node> = Api::all(self.client.clone());
+        Ok(node_api.list(&ListParams::default()).await.map_err(NodeBalancerError::KubeError)?.items)
+    }
+
+    fn watch_nodes(&self) -> WatchStream<Node> {
+        watcher(Api::all(self.client.clone()), ListParams::default()).map_err(NodeBalancerError::WatcherError).boxed()
+    }
+
+    async fn get_service(&self, namespace: &str, name: &str) -> Result<Service> {
+        let svc_api: Api<Service> = Api::namespaced(self.client.clone(), namespace);
+        match svc_api.get(name).await {
+            Ok(service) => Ok(service),
+            Err(kube::Error::Api(e)) if e.code == 404 => NodeBalancerError::ServiceNotFound.into(),
+            Err(e) => NodeBalancerError::KubeError(e).into(),
+        }
+    }
+
+    fn watch_services(&self) -> WatchStream<Service> {
+        watcher(Api::all(self.client.clone()), ListParams::default()).map_err(NodeBalancerError::WatcherError).boxed()
+    }
+
+    async fn list_pods(&self, namespace: &str, selector: &BTreeMap<String, String>) -> Result<Vec<Pod>> {
+        let pod_api: Api<Pod> = Api::namespaced(self.client.clone(), namespace);
+        let params = ListParams::default().labels(&Self::build_selector(selector));
+
+        Ok(pod_api.list(&params).await.map_err(NodeBalancerError::KubeError)?.items)
+    }
+
+    fn watch_pods(&self) -> WatchStream<Pod> {
+        watcher(Api::all(self.client.clone()), ListParams::default()).map_err(NodeBalancerError::WatcherError).boxed()
+    }
+
+    fn kube_client(&self) -> Option<Client> {
+        Some(self.client.clone())
+    }
+}
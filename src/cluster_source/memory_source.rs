@@ -0,0 +1,175 @@
+use crate::{Result, NodeBalancerError};
+use crate::cluster_source::{ClusterSource, WatchStream};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use futures_util::stream;
+use k8s_openapi::api::core::v1::{Node, Pod, Service};
+use kube::{Client, Resource};
+use kube_runtime::watcher::Event;
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+// Changes a watch can fall behind by before it's ended, making the router restart it from a fresh list
+const WATCH_BUFFER: usize = 1024;
+
+/// Nodes, services and pods held in memory, for running the router without a cluster: against fixed inputs
+/// in tests, or fed from another inventory. Changes reach watches as they're made.
+pub struct MemorySource {
+    nodes: Store<Node>,
+    services: Store<Service>,
+    pods: Store<Pod>,
+    client: Option<Client>,
+}
+
+// Objects of one kind by namespace and name. Nodes have an empty namespace.
+struct Store<K> {
+    objects: RwLock<BTreeMap<(String, String), K>>,
+    events: broadcast::Sender<Event<K>>,
+}
+
+impl MemorySource {
+    pub fn new() -> MemorySource {
+        MemorySource {
+            nodes: Store::new(),
+            services: Store::new(),
+            pods: Store::new(),
+            client: None,
+        }
+    }
+
+    /// Sends events to the cluster `client` talks to, while the nodes, services and pods come from memory
+    pub fn with_kube_client(mut self, client: Client) -> MemorySource {
+        self.client = Some(client);
+        self
+    }
+
+    /// Adds or replaces a node, by name
+    pub fn apply_node(&self, node: Node) {
+        self.nodes.apply(node);
+    }
+
+    pub fn delete_node(&self, name: &str) {
+        self.nodes.delete("", name);
+    }
+
+    /// Adds or replaces a service, by namespace and name
+    pub fn apply_service(&self, service: Service) {
+        self.services.apply(service);
+    }
+
+    pub fn delete_service(&self, namespace: &str, name: &str) {
+        self.services.delete(namespace, name);
+    }
+
+    /// Adds or replaces a pod, by namespace and name
+    pub fn apply_pod(&self, pod: Pod) {
+        self.pods.apply(pod);
+    }
+
+    pub fn delete_pod(&self, namespace: &str, name: &str) {
+        self.pods.delete(namespace, name);
+    }
+}
+
+impl Default for MemorySource {
+    fn default() -> MemorySource {
+        MemorySource::new()
+    }
+}
+
+#[async_trait]
+impl ClusterSource for MemorySource {
+    async fn list_nodes(&self) -> Result<Vec<Node>> {
+        Ok(self.nodes.list())
+    }
+
+    fn watch_nodes(&self) -> WatchStream<Node> {
+        self.nodes.watch()
+    }
+
+    async fn get_service(&self, namespace: &str, name: &str) -> Result<Service> {
+        self.services.get(namespace, name).ok_or(NodeBalancerError::ServiceNotFound)
+    }
+
+    fn watch_services(&self) -> WatchStream<Service> {
+        self.services.watch()
+    }
+
+    async fn list_pods(&self, namespace: &str, selector: &BTreeMap<String, String>) -> Result<Vec<Pod>> {
+        let pods = self.pods.list().into_iter()
+            .filter(|pod| pod.metadata.namespace.as_deref() == Some(namespace))
+            .filter(|pod| selector.iter().all(|(key, value)| pod.metadata.labels.get(key) == Some(value)))
+            .collect();
+
+        Ok(pods)
+    }
+
+    fn watch_pods(&self) -> WatchStream<Pod> {
+        self.pods.watch()
+    }
+
+    fn kube_client(&self) -> Option<Client> {
+        self.client.clone()
+    }
+
+    // Nothing changes between attempts unless the caller makes it
+    fn retries_lists(&self) -> bool {
+        false
+    }
+}
+
+impl<K: Resource + Clone + Send + Sync + 'static> Store<K> {
+    fn new() -> Store<K> {
+        Store {
+            objects: RwLock::new(BTreeMap::new()),
+            events: broadcast::channel(WATCH_BUFFER).0,
+        }
+    }
+
+    fn key(object: &K) -> (String, String) {
+        let meta = object.meta();
+        (meta.namespace.clone().unwrap_or_default(), meta.name.clone().unwrap_or_default())
+    }
+
+    fn apply(&self, object: K) {
+        // Sent under the lock, so watches see changes in the order they were made
+        let mut objects = self.objects.write();
+        objects.insert(Self::key(&object), object.clone());
+        let _ = self.events.send(Event::Applied(object));
+    }
+
+    fn delete(&self, namespace: &str, name: &str) {
+        let mut objects = self.objects.write();
+        if let Some(object) = objects.remove(&(namespace.to_owned(), name.to_owned())) {
+            let _ = self.events.send(Event::Deleted(object));
+        }
+    }
+
+    fn get(&self, namespace: &str, name: &str) -> Option<K> {
+        self.objects.read().get(&(namespace.to_owned(), name.to_owned())).cloned()
+    }
+
+    fn list(&self) -> Vec<K> {
+        self.objects.read().values().cloned().collect()
+    }
+
+    fn watch(&self) -> WatchStream<K> {
+        // Subscribed while holding the lock, so nothing falls between the list and the first change
+        let objects = self.objects.read();
+        let changes = self.events.subscribe();
+        let listed = Event::Restarted(objects.values().cloned().collect());
+        drop(objects);
+
+        let changes = stream::unfold(changes, |mut changes| async move {
+            match changes.recv().await {
+                Ok(event) => Some((Ok(event), changes)),
+                // Ending the watch has the router restart it from a fresh list
+                Err(RecvError::Lagged(_)) | Err(RecvError::Closed) => None,
+            }
+        });
+
+        stream::once(async move { Ok(listed) }).chain(changes).boxed()
+    }
+}
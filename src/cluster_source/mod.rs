@@ -0,0 +1,9 @@
+#[allow(clippy::module_inception)]
+mod cluster_source;
+pub use cluster_source::{ClusterSource, WatchStream};
+
+mod kubernetes_source;
+pub use kubernetes_source::KubernetesSource;
+
+mod memory_source;
+pub use memory_source::MemorySource;
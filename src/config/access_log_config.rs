@@ -0,0 +1,32 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessLogConfig {
+    // Only read at startup, like the rest of this section
+    pub enabled: bool,
+    // File to append to, stdout if unset
+    pub path: Option<PathBuf>,
+    // Fraction of connections logged, 0.0 to 1.0
+    pub sample_rate: f64,
+    // Log every connection that ended in an error, whatever the sample rate
+    pub always_log_errors: bool,
+    // Rotate the file once it reaches this many bytes, 0 to never rotate
+    pub max_size: u64,
+    // Rotated files to keep, named path.1 (newest) to path.N
+    pub max_files: usize,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> AccessLogConfig {
+        AccessLogConfig {
+            enabled: false,
+            path: None,
+            sample_rate: 1.0,
+            always_log_errors: true,
+            max_size: 100 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
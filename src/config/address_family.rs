@@ -0,0 +1,100 @@
+use std::net::IpAddr;
+
+named_enum! {
+    pub enum AddressFamily {
+        // Use node addresses in whatever order the node publishes them
+        Any => "any",
+        Ipv4 => "ipv4",
+        Ipv6 => "ipv6",
+        PreferIpv4 => "prefer-ipv4",
+        PreferIpv6 => "prefer-ipv6",
+        // Prefer the family the client connected over
+        SameAsClient => "same-as-client",
+    }
+}
+
+impl AddressFamily {
+    /// Orders candidate addresses for connecting, dropping any of a family that isn't allowed. The preferred
+    /// family comes first, alternating with the other family so happy eyeballs falls back quickly.
+    pub fn order(&self, addresses: Vec<IpAddr>, client: IpAddr) -> Vec<IpAddr> {
+        let prefer_v6 = match self {
+            AddressFamily::Ipv4 => return addresses.into_iter().filter(IpAddr::is_ipv4).collect(),
+            AddressFamily::Ipv6 => return addresses.into_iter().filter(IpAddr::is_ipv6).collect(),
+            AddressFamily::Any | AddressFamily::Unknown(_) => return addresses,
+            AddressFamily::PreferIpv4 => false,
+            AddressFamily::PreferIpv6 => true,
+            AddressFamily::SameAsClient => match client {
+                IpAddr::V4(_) => false,
+                IpAddr::V6(v6) => v6.to_ipv4_mapped().is_none(),
+            },
+        };
+
+        let (v6, v4): (Vec<IpAddr>, Vec<IpAddr>) = addresses.into_iter().partition(IpAddr::is_ipv6);
+        let (preferred, other) = if prefer_v6 { (v6, v4) } else { (v4, v6) };
+
+        let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+        let mut preferred = preferred.into_iter();
+        let mut other = other.into_iter();
+
+        loop {
+            match (preferred.next(), other.next()) {
+                (None, None) => return ordered,
+                (a, b) => ordered.extend(a.into_iter().chain(b)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    fn addresses() -> Vec<IpAddr> {
+        vec![ip("10.0.0.1"), ip("10.0.0.2"), ip("fd00::1"), ip("fd00::2")]
+    }
+
+    #[test]
+    fn single_family_drops_the_other() {
+        assert_eq!(AddressFamily::Ipv4.order(addresses(), ip("10.0.0.9")), vec![ip("10.0.0.1"), ip("10.0.0.2")]);
+        assert_eq!(AddressFamily::Ipv6.order(addresses(), ip("10.0.0.9")), vec![ip("fd00::1"), ip("fd00::2")]);
+    }
+
+    #[test]
+    fn any_keeps_the_node_order() {
+        assert_eq!(AddressFamily::Any.order(addresses(), ip("fd00::9")), addresses());
+    }
+
+    #[test]
+    fn preference_alternates_families() {
+        assert_eq!(
+            AddressFamily::PreferIpv6.order(addresses(), ip("10.0.0.9")),
+            vec![ip("fd00::1"), ip("10.0.0.1"), ip("fd00::2"), ip("10.0.0.2")],
+        );
+        assert_eq!(
+            AddressFamily::PreferIpv4.order(vec![ip("fd00::1"), ip("10.0.0.1"), ip("10.0.0.2")], ip("fd00::9")),
+            vec![ip("10.0.0.1"), ip("fd00::1"), ip("10.0.0.2")],
+        );
+    }
+
+    #[test]
+    fn same_as_client_treats_mapped_addresses_as_ipv4() {
+        assert_eq!(AddressFamily::SameAsClient.order(addresses(), ip("fd00::9"))[0], ip("fd00::1"));
+        assert_eq!(AddressFamily::SameAsClient.order(addresses(), ip("10.0.0.9"))[0], ip("10.0.0.1"));
+        assert_eq!(AddressFamily::SameAsClient.order(addresses(), ip("::ffff:10.0.0.9"))[0], ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn parses_every_name() {
+        for name in AddressFamily::NAMES {
+            let family = AddressFamily::from(name.to_string());
+            assert!(!matches!(family, AddressFamily::Unknown(_)));
+            assert_eq!(family.to_string(), *name);
+        }
+
+        assert_eq!(AddressFamily::from("ipv5".to_owned()), AddressFamily::Unknown("ipv5".to_owned()));
+    }
+}
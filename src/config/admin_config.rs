@@ -0,0 +1,32 @@
+use serde::Deserialize;
+use std::net::SocketAddr;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    // Serves /metrics, /ready, /healthz and /log_filter. Only read at startup.
+    pub enabled: bool,
+    pub listen_addr: String,
+    // Lets PUT /log_filter change the log filter. Off by default, as the admin server has no authentication
+    // and listens on all interfaces so it can be scraped and probed.
+    pub log_filter_writable: bool,
+}
+
+impl Default for AdminConfig {
+    fn default() -> AdminConfig {
+        AdminConfig {
+            enabled: true,
+            listen_addr: "0.0.0.0:9090".to_owned(),
+            log_filter_writable: false,
+        }
+    }
+}
+
+impl AdminConfig {
+    /// The port the admin server listens on, if it's enabled and its address is valid
+    pub fn port(&self) -> Option<u16> {
+        self.listen_addr.parse::<SocketAddr>().ok()
+            .filter(|_| self.enabled)
+            .map(|addr| addr.port())
+    }
+}
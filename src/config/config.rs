@@ -0,0 +1,194 @@
+use serde::Deserialize;
+use crate::config::{ServiceRef, ListenerConfig, DynamicListenersConfig, Strategy, AddressFamily, DataPath, Timeouts, NodeAddressConfig, HealthCheckConfig, TopologyConfig, NodeWeightsConfig, SourceRanges, LimitsConfig, DrainConfig, ShutdownConfig, LeaderElectionConfig, ServiceStatusConfig, EventsConfig, CustomResourceConfig, IoUringConfig, AccessLogConfig, LoggingConfig, OtlpConfig, AdminConfig};
+use crate::backoff::BackoffPolicy;
+use crate::custom_resource::NodeBalancer;
+use crate::{Result, NodeBalancerError};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // Used by listeners that don't set their own address
+    pub listen_addr: String,
+    // Whether listeners on an IPv6 address also accept IPv4 clients
+    pub dual_stack: bool,
+    // Used by listeners that don't set their own strategy
+    pub strategy: Strategy,
+    // Used by listeners that don't set their own address family
+    pub address_family: AddressFamily,
+    // Used by listeners that don't set their own data path
+    pub data_path: DataPath,
+    pub service: ServiceRef,
+    pub listeners: Vec<ListenerConfig>,
+    // Listeners derived from the service's ports, alongside the ones above
+    pub dynamic_listeners: DynamicListenersConfig,
+    // Used by listeners that don't set their own timeouts
+    pub timeouts: Timeouts,
+    // Used by listeners that don't set their own source ranges
+    pub source_ranges: SourceRanges,
+    pub limits: LimitsConfig,
+    pub node_addresses: NodeAddressConfig,
+    pub health_check: HealthCheckConfig,
+    pub topology: TopologyConfig,
+    pub node_weights: NodeWeightsConfig,
+    pub drain: DrainConfig,
+    pub shutdown: ShutdownConfig,
+    pub leader_election: LeaderElectionConfig,
+    pub service_status: ServiceStatusConfig,
+    pub events: EventsConfig,
+    pub custom_resource: CustomResourceConfig,
+    pub io_uring: IoUringConfig,
+    pub access_log: AccessLogConfig,
+    pub logging: LoggingConfig,
+    pub otlp: OtlpConfig,
+    pub admin: AdminConfig,
+    pub backoff: BackoffPolicy,
+}
+
+// Env vars take precedence over the config file. These are the same names the balancer has always used,
+// so deployments without a config file keep working.
+#[derive(Debug, Default, Deserialize)]
+struct EnvOverrides {
+    config_file: Option<PathBuf>,
+    service_namespace: Option<String>,
+    service_name: Option<String>,
+    ports: Option<Vec<u16>>,
+    listen_addr: Option<String>,
+    strategy: Option<Strategy>,
+}
+
+impl Config {
+    pub fn load() -> Result<Config> {
+        let config = Self::load_unchecked()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Loads the config with a NodeBalancer resource laid over the config file and env vars
+    pub fn load_with_resource(resource: &NodeBalancer) -> Result<Config> {
+        let mut config = Self::load_unchecked()?;
+        let problems = resource.apply(&mut config);
+        config.validate_with_resource(problems)?;
+
+        Ok(config)
+    }
+
+    fn load_unchecked() -> Result<Config> {
+        let env: EnvOverrides = envy::from_env().map_err(NodeBalancerError::EnvError)?;
+
+        let mut config = match &env.config_file {
+            Some(path) => Self::from_file(path)?,
+            None => Config::default(),
+        };
+
+        env.apply(&mut config);
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| NodeBalancerError::ConfigFileError(path.to_owned(), e))?;
+
+        toml::from_str(&contents).map_err(NodeBalancerError::ConfigParseError)
+    }
+
+    /// Sections only read at startup that differ in `other`, so a reload can say they weren't applied
+    pub fn startup_only_changes(&self, other: &Config) -> Vec<&'static str> {
+        let sections = [
+            ("admin", self.admin != other.admin),
+            ("access_log", self.access_log != other.access_log),
+            ("custom_resource", self.custom_resource != other.custom_resource),
+            ("io_uring", self.io_uring != other.io_uring),
+            ("leader_election", self.leader_election != other.leader_election),
+            ("logging.format", self.logging.format != other.logging.format),
+            ("otlp", self.otlp != other.otlp),
+        ];
+
+        sections.iter().filter(|(_, changed)| *changed).map(|(name, _)| *name).collect()
+    }
+
+    /// Carries the sections only read at startup over from `running`, so a reload publishes only what it applies
+    pub fn keep_startup_only(&mut self, running: &Config) {
+        self.admin = running.admin.clone();
+        self.access_log = running.access_log.clone();
+        self.custom_resource = running.custom_resource.clone();
+        self.io_uring = running.io_uring.clone();
+        self.leader_election = running.leader_election.clone();
+        self.logging.format = running.logging.format.clone();
+        self.otlp = running.otlp.clone();
+    }
+
+    pub fn file_path() -> Option<PathBuf> {
+        std::env::var_os("CONFIG_FILE").map(PathBuf::from)
+    }
+
+    pub fn listener(&self, port: u16) -> Option<&ListenerConfig> {
+        self.listeners.iter().find(|listener| listener.port == port)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            listen_addr: "0.0.0.0".to_owned(),
+            dual_stack: true,
+            strategy: Strategy::Random,
+            address_family: AddressFamily::Any,
+            data_path: DataPath::Copy,
+            service: ServiceRef::default(),
+            listeners: Vec::new(),
+            dynamic_listeners: DynamicListenersConfig::default(),
+            timeouts: Timeouts {
+                connect: Some(Duration::from_secs(10)),
+                happy_eyeballs_delay: Some(Duration::from_millis(250)),
+                idle: Some(Duration::from_secs(3600)),
+                half_close: Some(Duration::from_secs(60)),
+                max_lifetime: None,
+            },
+            source_ranges: SourceRanges::default(),
+            limits: LimitsConfig::default(),
+            node_addresses: NodeAddressConfig::default(),
+            health_check: HealthCheckConfig::default(),
+            topology: TopologyConfig::default(),
+            node_weights: NodeWeightsConfig::default(),
+            drain: DrainConfig::default(),
+            shutdown: ShutdownConfig::default(),
+            leader_election: LeaderElectionConfig::default(),
+            service_status: ServiceStatusConfig::default(),
+            events: EventsConfig::default(),
+            custom_resource: CustomResourceConfig::default(),
+            io_uring: IoUringConfig::default(),
+            access_log: AccessLogConfig::default(),
+            logging: LoggingConfig::default(),
+            otlp: OtlpConfig::default(),
+            admin: AdminConfig::default(),
+            backoff: BackoffPolicy::default(),
+        }
+    }
+}
+
+impl EnvOverrides {
+    fn apply(self, config: &mut Config) {
+        if let Some(namespace) = self.service_namespace {
+            config.service.namespace = namespace;
+        }
+
+        if let Some(name) = self.service_name {
+            config.service.name = name;
+        }
+
+        if let Some(ports) = self.ports {
+            config.listeners = ports.into_iter().map(ListenerConfig::new).collect();
+        }
+
+        if let Some(listen_addr) = self.listen_addr {
+            config.listen_addr = listen_addr;
+        }
+
+        if let Some(strategy) = self.strategy {
+            config.strategy = strategy;
+        }
+    }
+}
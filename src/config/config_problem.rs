@@ -0,0 +1,131 @@
+use thiserror::Error;
+use crate::config::{AddressFamily, DataPath, LogFormat, NodeAddressConfig, OverLimit, Strategy};
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ConfigProblem {
+    #[error("service name is not set, set service.name in the config file or SERVICE_NAME")]
+    MissingServiceName,
+
+    #[error("service namespace is empty")]
+    MissingServiceNamespace,
+
+    #[error("listen_addr {addr:?} in {location} is not an IP address")]
+    InvalidListenAddr {
+        location: String,
+        addr: String,
+    },
+
+    #[error("no listeners configured, add [[listeners]] to the config file, set PORTS or enable dynamic_listeners")]
+    NoListeners,
+
+    #[error("listener port 0 is not allowed")]
+    ZeroListenerPort,
+
+    #[error("port {0} is used by more than one listener")]
+    DuplicateListenerPort(u16),
+
+    #[error("unknown strategy {name:?} in {location}, expected one of: {}", Strategy::NAMES.join(", "))]
+    UnknownStrategy {
+        location: String,
+        name: String,
+    },
+
+    #[error("service_port of listener {0} is an empty name")]
+    EmptyPortName(u16),
+
+    #[error("dynamic_listeners.remap key {0:?} is not a port number or name")]
+    InvalidRemapKey(String),
+
+    #[error("dynamic_listeners.remap maps service port {0} to listen port 0")]
+    ZeroRemapTarget(String),
+
+    #[error("node_addresses.preference is empty")]
+    NoAddressTypes,
+
+    #[error("unknown node address type {0:?} in node_addresses.preference, expected one of: {}", NodeAddressConfig::ADDRESS_TYPES.join(", "))]
+    UnknownAddressType(String),
+
+    #[error("unknown address family {name:?} in {location}, expected one of: {}", AddressFamily::NAMES.join(", "))]
+    UnknownAddressFamily {
+        location: String,
+        name: String,
+    },
+
+    #[error("unknown data path {name:?} in {location}, expected one of: {}", DataPath::NAMES.join(", "))]
+    UnknownDataPath {
+        location: String,
+        name: String,
+    },
+
+    #[error("unknown limits.over_limit {0:?}, expected one of: {}", OverLimit::NAMES.join(", "))]
+    UnknownOverLimit(String),
+
+    #[error("limits.rate_per_ip must be a positive number, got {0}")]
+    InvalidRate(f64),
+
+    #[error("topology.min_local_share must be between 0 and 1, got {0}")]
+    InvalidLocalShare(f64),
+
+    #[error("access_log.sample_rate must be between 0 and 1, got {0}")]
+    InvalidSampleRate(f64),
+
+    #[error("leader_election.lease_name is empty")]
+    EmptyLeaseName,
+
+    #[error("leader_election.{0} must be shorter than leader_election.{1}")]
+    NotShorterThan(&'static str, &'static str),
+
+    #[error("custom_resource is enabled but custom_resource.name is empty")]
+    EmptyResourceName,
+
+    #[error("service_status is enabled but service_status.ingress is empty")]
+    NoIngress,
+
+    #[error("service_status.ingress entry {0:?} is not an IP address or hostname")]
+    InvalidIngress(String),
+
+    #[error("unknown logging.format {0:?}, expected one of: {}", LogFormat::NAMES.join(", "))]
+    UnknownLogFormat(String),
+
+    #[error("logging.filter {filter:?} is invalid: {reason}")]
+    InvalidLogFilter {
+        filter: String,
+        reason: String,
+    },
+
+    #[error("otlp.endpoint {0:?} is not an http:// or https:// URL")]
+    InvalidOtlpEndpoint(String),
+
+    #[error("admin.listen_addr {0:?} is not an IP address and port")]
+    InvalidAdminAddr(String),
+
+    #[error("admin port {0} is also used by a listener")]
+    AdminPortConflict(u16),
+
+    #[error("{0} must be greater than zero")]
+    MustBePositive(String),
+
+    #[error("{location} entry {range:?} is not a CIDR range")]
+    InvalidSourceRange {
+        location: String,
+        range: String,
+    },
+
+    #[error("{location} {value:?} is not a duration such as 10s")]
+    InvalidDuration {
+        location: String,
+        value: String,
+    },
+
+    #[error("backoff.multiplier must be a finite number of at least 1, got {0}")]
+    InvalidMultiplier(f64),
+
+    #[error("backoff.jitter must be between 0 and 1, got {0}")]
+    InvalidJitter(f64),
+
+    #[error("backoff.initial ({initial:?}) is greater than backoff.max ({max:?})")]
+    BackoffInitialAboveMax {
+        initial: std::time::Duration,
+        max: std::time::Duration,
+    },
+}
@@ -0,0 +1,22 @@
+use serde::Deserialize;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CustomResourceConfig {
+    // Take the service, listeners, strategy, health checks and source ranges from a NodeBalancer resource,
+    // laid over the rest of the config. Only read at startup, like the rest of this section.
+    pub enabled: bool,
+    pub name: String,
+    // Defaults to the balanced service's namespace
+    pub namespace: Option<String>,
+}
+
+impl Default for CustomResourceConfig {
+    fn default() -> CustomResourceConfig {
+        CustomResourceConfig {
+            enabled: false,
+            name: "node-balancer".to_owned(),
+            namespace: None,
+        }
+    }
+}
@@ -0,0 +1,10 @@
+named_enum! {
+    /// How bytes are moved between the client and the node
+    pub enum DataPath {
+        // Read into a buffer in userspace and write it out again
+        Copy => "copy",
+        // Move bytes through a pipe with splice(2) without copying them into userspace. Linux only, anywhere
+        // else or if the kernel refuses, connections fall back to copy.
+        Splice => "splice",
+    }
+}
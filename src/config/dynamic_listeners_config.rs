@@ -0,0 +1,69 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use crate::router::MappedPort;
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DynamicListenersConfig {
+    // Open a listener for every port the service exposes
+    pub enabled: bool,
+    // Added to the service port to get the listen port
+    pub offset: i32,
+    // service port number or name -> listen port, takes precedence over offset
+    pub remap: BTreeMap<String, u16>,
+}
+
+impl DynamicListenersConfig {
+    pub fn listen_port(&self, service_port: &MappedPort) -> Option<u16> {
+        let remapped = service_port.name.as_ref()
+            .and_then(|name| self.remap.get(name))
+            .or_else(|| self.remap.get(&service_port.port.to_string()));
+
+        if let Some(port) = remapped {
+            return Some(*port);
+        }
+
+        u16::try_from(i32::from(service_port.port) + self.offset).ok()
+            .filter(|port| *port != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Protocol;
+
+    fn port(name: Option<&str>, port: u16) -> MappedPort {
+        MappedPort::new(name.map(str::to_owned), Protocol::Tcp, port, 30000)
+    }
+
+    #[test]
+    fn applies_offset() {
+        let config = DynamicListenersConfig { enabled: true, offset: 8000, ..DynamicListenersConfig::default() };
+        assert_eq!(config.listen_port(&port(None, 80)), Some(8080));
+    }
+
+    #[test]
+    fn rejects_ports_out_of_range() {
+        let config = DynamicListenersConfig { enabled: true, offset: -80, ..DynamicListenersConfig::default() };
+        assert_eq!(config.listen_port(&port(None, 80)), None);
+        assert_eq!(config.listen_port(&port(None, 40)), None);
+
+        let config = DynamicListenersConfig { enabled: true, offset: 1000, ..DynamicListenersConfig::default() };
+        assert_eq!(config.listen_port(&port(None, 65000)), None);
+    }
+
+    #[test]
+    fn remap_by_name_then_number_takes_precedence() {
+        let mut config = DynamicListenersConfig { enabled: true, offset: 8000, ..DynamicListenersConfig::default() };
+        config.remap.insert("https".to_owned(), 8443);
+        config.remap.insert("443".to_owned(), 9443);
+        config.remap.insert("80".to_owned(), 9080);
+
+        assert_eq!(config.listen_port(&port(Some("https"), 443)), Some(8443));
+        assert_eq!(config.listen_port(&port(Some("web"), 443)), Some(9443));
+        assert_eq!(config.listen_port(&port(Some("http"), 80)), Some(9080));
+        assert_eq!(config.listen_port(&port(Some("http"), 81)), Some(8081));
+    }
+}
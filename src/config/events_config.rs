@@ -0,0 +1,22 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventsConfig {
+    // Emit Warning events on the balanced service and its nodes for routing problems while this replica leads
+    pub enabled: bool,
+    // Repeats of a problem within this long of the last update are counted into its event rather than each
+    // updating it
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+}
+
+impl Default for EventsConfig {
+    fn default() -> EventsConfig {
+        EventsConfig {
+            enabled: false,
+            interval: Duration::from_secs(300),
+        }
+    }
+}
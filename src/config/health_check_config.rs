@@ -0,0 +1,32 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthCheckConfig {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    // Consecutive successful probes before an unhealthy node is used again
+    pub healthy_threshold: u32,
+    // Consecutive failed probes before a node stops receiving connections
+    pub unhealthy_threshold: u32,
+    // For services with externalTrafficPolicy Local, probe the healthCheckNodePort of each node hosting pods
+    // instead of a NodePort. kube-proxy answers 200 there only while the node has ready pods of the service.
+    pub health_check_node_port: bool,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> HealthCheckConfig {
+        HealthCheckConfig {
+            enabled: false,
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(2),
+            healthy_threshold: 2,
+            unhealthy_threshold: 3,
+            health_check_node_port: true,
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use serde::Deserialize;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IoUringConfig {
+    // Connect to nodes and move the bytes on io_uring worker threads instead of the main runtime. Accepting
+    // stays on epoll, since tokio-uring 0.4 can only accept on sockets it binds itself, so the listeners
+    // couldn't keep their dual stack setting or be handed between runtimes on reload. Needs a build with the
+    // io-uring feature and a kernel that supports io_uring, otherwise connections stay on epoll. Only read
+    // at startup.
+    pub enabled: bool,
+    // Worker threads, each with its own ring
+    pub workers: usize,
+    // Submission queue size of each ring
+    pub entries: u32,
+    // Size of the buffers each direction of a connection reads into. Buffers are ordinary heap buffers
+    // reused across connections, not registered with the ring: tokio-uring 0.4 has no fixed buffer ops.
+    pub buffer_size: usize,
+}
+
+impl Default for IoUringConfig {
+    fn default() -> IoUringConfig {
+        IoUringConfig {
+            enabled: false,
+            workers: 2,
+            entries: 256,
+            buffer_size: 16 * 1024,
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeaderElectionConfig {
+    // Elect one replica to perform cluster-mutating duties. When disabled this replica always leads, so only
+    // run one. Only read at startup, like the rest of this section.
+    pub enabled: bool,
+    pub lease_name: String,
+    // Defaults to the balanced service's namespace
+    pub lease_namespace: Option<String>,
+    // Defaults to HOSTNAME, which is the pod name in a cluster
+    pub identity: Option<String>,
+    // How long other replicas wait after the last renewal before taking over
+    #[serde(with = "humantime_serde")]
+    pub lease_duration: Duration,
+    // How long the leader keeps leading without managing to renew. Shorter than lease_duration, so it steps
+    // down before anyone else can take over.
+    #[serde(with = "humantime_serde")]
+    pub renew_deadline: Duration,
+    // Time between attempts to acquire or renew the lease
+    #[serde(with = "humantime_serde")]
+    pub retry_period: Duration,
+}
+
+impl Default for LeaderElectionConfig {
+    fn default() -> LeaderElectionConfig {
+        LeaderElectionConfig {
+            enabled: false,
+            lease_name: "node-balancer".to_owned(),
+            lease_namespace: None,
+            identity: None,
+            lease_duration: Duration::from_secs(15),
+            renew_deadline: Duration::from_secs(10),
+            retry_period: Duration::from_secs(2),
+        }
+    }
+}
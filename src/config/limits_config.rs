@@ -0,0 +1,38 @@
+use serde::Deserialize;
+use crate::config::OverLimit;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    // Concurrent connections across every listener
+    pub max_connections: Option<usize>,
+    // Concurrent connections per listener, for listeners that don't set their own max_connections
+    pub max_connections_per_listener: Option<usize>,
+    // Concurrent connections from a single client IP across every listener
+    pub max_connections_per_ip: Option<usize>,
+    // New connections per second from a single client IP, refilling a bucket of burst_per_ip
+    pub rate_per_ip: Option<f64>,
+    pub burst_per_ip: u32,
+    pub over_limit: OverLimit,
+    // How long a queued connection waits for a slot before being rejected
+    #[serde(with = "humantime_serde")]
+    pub queue_timeout: Duration,
+    // Connections waiting at once, any more are rejected
+    pub queue_size: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> LimitsConfig {
+        LimitsConfig {
+            max_connections: None,
+            max_connections_per_listener: None,
+            max_connections_per_ip: None,
+            rate_per_ip: None,
+            burst_per_ip: 20,
+            over_limit: OverLimit::Reject,
+            queue_timeout: Duration::from_secs(1),
+            queue_size: 1024,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use serde::Deserialize;
+use crate::config::{Config, PortRef, Strategy, AddressFamily, DataPath, Timeouts, SourceRanges};
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerConfig {
+    pub port: u16,
+    pub listen_addr: Option<String>,
+    // Service port to forward to by number or name, defaults to the listen port
+    pub service_port: Option<PortRef>,
+    pub strategy: Option<Strategy>,
+    pub address_family: Option<AddressFamily>,
+    pub data_path: Option<DataPath>,
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    pub source_ranges: Option<SourceRanges>,
+    // Concurrent connections on this listener, overriding limits.max_connections_per_listener
+    pub max_connections: Option<usize>,
+}
+
+impl ListenerConfig {
+    pub fn new(port: u16) -> ListenerConfig {
+        ListenerConfig {
+            port,
+            listen_addr: None,
+            service_port: None,
+            strategy: None,
+            address_family: None,
+            data_path: None,
+            timeouts: Timeouts::default(),
+            source_ranges: None,
+            max_connections: None,
+        }
+    }
+
+    pub fn service_port(&self) -> PortRef {
+        self.service_port.clone().unwrap_or(PortRef::Number(self.port))
+    }
+
+    pub fn listen_addr<'a>(&'a self, config: &'a Config) -> &'a str {
+        self.listen_addr.as_deref().unwrap_or(&config.listen_addr)
+    }
+
+    pub fn address_family(&self, config: &Config) -> AddressFamily {
+        self.address_family.clone().unwrap_or_else(|| config.address_family.clone())
+    }
+
+    pub fn data_path(&self, config: &Config) -> DataPath {
+        self.data_path.clone().unwrap_or_else(|| config.data_path.clone())
+    }
+
+    pub fn strategy(&self, config: &Config) -> Strategy {
+        self.strategy.clone().unwrap_or_else(|| config.strategy.clone())
+    }
+
+    pub fn timeouts(&self, config: &Config) -> Timeouts {
+        self.timeouts.or(&config.timeouts)
+    }
+
+    pub fn source_ranges<'a>(&'a self, config: &'a Config) -> &'a SourceRanges {
+        self.source_ranges.as_ref().unwrap_or(&config.source_ranges)
+    }
+
+    pub fn max_connections(&self, config: &Config) -> Option<usize> {
+        self.max_connections.or(config.limits.max_connections_per_listener)
+    }
+}
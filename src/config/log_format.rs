@@ -0,0 +1,9 @@
+named_enum! {
+    /// How log lines are written to stderr
+    pub enum LogFormat {
+        // Human readable, one line per event with its spans' fields
+        Text => "text",
+        // One JSON object per event, with the current span and its parents
+        Json => "json",
+    }
+}
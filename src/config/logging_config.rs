@@ -0,0 +1,21 @@
+use serde::Deserialize;
+use crate::config::LogFormat;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    // Only read at startup
+    pub format: LogFormat,
+    // Directives in RUST_LOG syntax, e.g. "info,node_balancer::proxy=debug". RUST_LOG takes precedence at
+    // startup, after that changes here are applied on reload.
+    pub filter: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> LoggingConfig {
+        LoggingConfig {
+            format: LogFormat::Text,
+            filter: "info".to_owned(),
+        }
+    }
+}
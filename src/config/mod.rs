@@ -0,0 +1,94 @@
+#[macro_use]
+mod named_enum;
+
+#[allow(clippy::module_inception)]
+mod config;
+pub use config::Config;
+
+mod validate;
+
+mod config_problem;
+pub use config_problem::ConfigProblem;
+
+mod service_ref;
+pub use service_ref::ServiceRef;
+
+mod listener_config;
+pub use listener_config::ListenerConfig;
+
+mod port_ref;
+pub use port_ref::PortRef;
+
+mod address_family;
+pub use address_family::AddressFamily;
+
+mod strategy;
+pub use strategy::Strategy;
+
+mod data_path;
+pub use data_path::DataPath;
+
+mod dynamic_listeners_config;
+pub use dynamic_listeners_config::DynamicListenersConfig;
+
+mod timeouts;
+pub use timeouts::Timeouts;
+
+mod source_ranges;
+pub use source_ranges::SourceRanges;
+
+mod limits_config;
+pub use limits_config::LimitsConfig;
+
+mod over_limit;
+pub use over_limit::OverLimit;
+
+mod node_address_config;
+pub use node_address_config::NodeAddressConfig;
+
+mod drain_config;
+pub use drain_config::DrainConfig;
+
+mod shutdown_config;
+pub use shutdown_config::ShutdownConfig;
+
+mod service_status_config;
+pub use service_status_config::ServiceStatusConfig;
+
+mod custom_resource_config;
+pub use custom_resource_config::CustomResourceConfig;
+
+mod events_config;
+pub use events_config::EventsConfig;
+
+mod leader_election_config;
+pub use leader_election_config::LeaderElectionConfig;
+
+mod io_uring_config;
+pub use io_uring_config::IoUringConfig;
+
+mod access_log_config;
+pub use access_log_config::AccessLogConfig;
+
+mod logging_config;
+pub use logging_config::LoggingConfig;
+
+mod log_format;
+pub use log_format::LogFormat;
+
+mod otlp_config;
+pub use otlp_config::OtlpConfig;
+
+mod admin_config;
+pub use admin_config::AdminConfig;
+
+mod node_weights_config;
+pub use node_weights_config::NodeWeightsConfig;
+
+mod topology_config;
+pub use topology_config::TopologyConfig;
+
+mod health_check_config;
+pub use health_check_config::HealthCheckConfig;
+
+pub mod reload;
@@ -0,0 +1,41 @@
+/// Defines a config enum written as one of a fixed set of names. A name that isn't one of them becomes
+/// `Unknown` instead of failing to parse, so validation can report it alongside every other problem in the
+/// config rather than serde stopping at the first.
+macro_rules! named_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($variant:ident => $text:literal,)+
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize)]
+        #[serde(from = "String")]
+        pub enum $name {
+            $($variant,)+
+            Unknown(String),
+        }
+
+        impl $name {
+            pub const NAMES: &'static [&'static str] = &[$($text),+];
+        }
+
+        impl From<String> for $name {
+            fn from(name: String) -> $name {
+                match name.as_str() {
+                    $($text => $name::$variant,)+
+                    _ => $name::Unknown(name),
+                }
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $($name::$variant => write!(f, $text),)+
+                    $name::Unknown(name) => write!(f, "{}", name),
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,29 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeAddressConfig {
+    // Node address types in order of preference, later types are used only if a node has none of the earlier
+    pub preference: Vec<String>,
+    // Node annotation whose value (comma separated IPs or hostnames) replaces the published addresses.
+    // Empty to disable.
+    pub annotation: String,
+    // How long resolved hostnames are cached
+    #[serde(with = "humantime_serde")]
+    pub dns_ttl: Duration,
+}
+
+impl NodeAddressConfig {
+    pub const ADDRESS_TYPES: &'static [&'static str] = &["InternalIP", "ExternalIP", "Hostname", "InternalDNS", "ExternalDNS"];
+}
+
+impl Default for NodeAddressConfig {
+    fn default() -> NodeAddressConfig {
+        NodeAddressConfig {
+            preference: vec!["InternalIP".to_owned()],
+            annotation: "node-balancer.io/address".to_owned(),
+            dns_ttl: Duration::from_secs(30),
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use serde::Deserialize;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeWeightsConfig {
+    // Node annotation whose whole number value replaces the node's weight, 0 to take it out of rotation.
+    // Empty to disable. Weights are capped at 65535.
+    pub annotation: String,
+    // Caps the weight a node gets from the pods it hosts
+    pub max_pods: Option<u32>,
+    // Weight of nodes hosting no routable pods, which kube-proxy forwards on. 0 leaves them out.
+    pub empty_node_weight: u32,
+}
+
+impl Default for NodeWeightsConfig {
+    fn default() -> NodeWeightsConfig {
+        NodeWeightsConfig {
+            annotation: "node-balancer.io/weight".to_owned(),
+            max_pods: None,
+            empty_node_weight: 0,
+        }
+    }
+}
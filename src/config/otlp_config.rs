@@ -0,0 +1,31 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OtlpConfig {
+    // Push metrics and spans to an OpenTelemetry collector. Needs a build with the otlp feature. Only read at
+    // startup.
+    pub enabled: bool,
+    // Base URL of the collector's OTLP/HTTP receiver, /v1/metrics and /v1/traces are appended
+    pub endpoint: String,
+    #[serde(with = "humantime_serde")]
+    pub export_interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    // Added to the resource alongside the ones identifying the balancer and its service, overriding them
+    pub resource_attributes: BTreeMap<String, String>,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> OtlpConfig {
+        OtlpConfig {
+            enabled: false,
+            endpoint: "http://localhost:4318".to_owned(),
+            export_interval: Duration::from_secs(60),
+            timeout: Duration::from_secs(10),
+            resource_attributes: BTreeMap::new(),
+        }
+    }
+}
@@ -0,0 +1,9 @@
+named_enum! {
+    /// What happens to a connection accepted while a limit is reached
+    pub enum OverLimit {
+        // Close it straight away
+        Reject => "reject",
+        // Hold it for up to the queue timeout, waiting for a slot to free up
+        Queue => "queue",
+    }
+}
@@ -0,0 +1,19 @@
+use serde::Deserialize;
+use std::fmt;
+
+/// A service port, either by number or by the name given to it in the Service
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(untagged)]
+pub enum PortRef {
+    Number(u16),
+    Name(String),
+}
+
+impl fmt::Display for PortRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortRef::Number(number) => write!(f, "{}", number),
+            PortRef::Name(name) => write!(f, "{:?}", name),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{Config, Result, NodeBalancerError};
+use crate::custom_resource::NodeBalancer;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+type Resource = watch::Receiver<Option<Arc<NodeBalancer>>>;
+
+/// Reloads the config on SIGHUP, when the config file is modified or when the NodeBalancer resource followed
+/// through `resource` changes, publishing changes through `tx`
+pub fn spawn(tx: watch::Sender<Arc<Config>>, mut resource: Option<Resource>) -> Result<JoinHandle<()>> {
+    let mut hangup = signal(SignalKind::hangup()).map_err(NodeBalancerError::IOError)?;
+
+    Ok(tokio::spawn(async move {
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+        let mut last_modified = modified_time();
+
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => {
+                    info!("Got SIGHUP, reloading config");
+                }
+
+                _ = poll.tick() => {
+                    let modified = modified_time();
+                    if modified == last_modified {
+                        continue;
+                    }
+
+                    last_modified = modified;
+                    info!("Config file changed, reloading config");
+                }
+
+                res = resource_changed(&mut resource) => if res.is_ok() {
+                    info!("NodeBalancer resource changed, reloading config");
+                } else {
+                    resource = None;
+                    continue;
+                }
+            }
+
+            let current = resource.as_ref().and_then(|resource| resource.borrow().clone());
+            reload(&tx, current.as_deref());
+        }
+    }))
+}
+
+// Never completes without a resource to follow
+async fn resource_changed(resource: &mut Option<Resource>) -> std::result::Result<(), watch::error::RecvError> {
+    match resource {
+        Some(resource) => resource.changed().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn reload(tx: &watch::Sender<Arc<Config>>, resource: Option<&NodeBalancer>) {
+    let loaded = match resource {
+        Some(resource) => Config::load_with_resource(resource),
+        None => Config::load(),
+    };
+
+    let mut config = match loaded {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to reload config, keeping the previous one: {}", e);
+            return;
+        }
+    };
+
+    let running = Arc::clone(&tx.borrow());
+    let ignored = running.startup_only_changes(&config);
+    if !ignored.is_empty() {
+        warn!("Changes to {} are only applied on restart", ignored.join(", "));
+        config.keep_startup_only(&running);
+    }
+
+    if *running == config {
+        info!("Config unchanged");
+        return;
+    }
+
+    if tx.send(Arc::new(config)).is_err() {
+        error!("Config reloaded but nothing is listening for changes");
+    } else {
+        info!("Applied new config");
+    }
+}
+
+fn modified_time() -> Option<SystemTime> {
+    let path = Config::file_path()?;
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
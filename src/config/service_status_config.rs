@@ -0,0 +1,11 @@
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServiceStatusConfig {
+    // Publish ingress in status.loadBalancer of the balanced service while this replica leads, so it shows an
+    // external IP rather than <pending>. Only applies to services of type LoadBalancer.
+    pub enabled: bool,
+    // IPs or hostnames clients reach the balancer on
+    pub ingress: Vec<String>,
+}
@@ -0,0 +1,23 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    // How long to report not ready before no longer accepting, giving whatever sends us traffic time to
+    // notice. Zero skips straight to closing the listeners.
+    #[serde(with = "humantime_serde")]
+    pub readiness_delay: Duration,
+    // How long open connections get to finish before they are closed
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> ShutdownConfig {
+        ShutdownConfig {
+            readiness_delay: Duration::from_secs(0),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use serde::Deserialize;
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourceRanges {
+    // Clients must be in one of these, unless both this and the service's ranges are empty
+    pub allow: Vec<IpNet>,
+    // Clients in these are refused even if they are also allowed
+    pub deny: Vec<IpNet>,
+    // Also allow the service's spec.loadBalancerSourceRanges
+    pub from_service: bool,
+}
+
+impl SourceRanges {
+    pub fn allows(&self, ip: IpAddr, service_ranges: &[IpNet]) -> bool {
+        // IPv4 clients of a dual-stack listener show up as IPv4-mapped IPv6 addresses
+        let ip = ip.to_canonical();
+
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+
+        let service_ranges = if self.from_service { service_ranges } else { &[] };
+        if self.allow.is_empty() && service_ranges.is_empty() {
+            return true;
+        }
+
+        self.allow.iter().chain(service_ranges).any(|net| net.contains(&ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    fn nets(nets: &[&str]) -> Vec<IpNet> {
+        nets.iter().map(|net| net.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn allows_everyone_without_ranges() {
+        assert!(SourceRanges::default().allows(ip("192.0.2.1"), &[]));
+        assert!(SourceRanges::default().allows(ip("2001:db8::1"), &[]));
+    }
+
+    #[test]
+    fn allow_list_restricts() {
+        let ranges = SourceRanges { allow: nets(&["10.0.0.0/8"]), ..SourceRanges::default() };
+
+        assert!(ranges.allows(ip("10.1.2.3"), &[]));
+        assert!(!ranges.allows(ip("192.0.2.1"), &[]));
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let ranges = SourceRanges {
+            allow: nets(&["10.0.0.0/8"]),
+            deny: nets(&["10.0.0.0/24"]),
+            ..SourceRanges::default()
+        };
+
+        assert!(!ranges.allows(ip("10.0.0.5"), &[]));
+        assert!(ranges.allows(ip("10.0.1.5"), &[]));
+    }
+
+    #[test]
+    fn service_ranges_only_count_when_enabled() {
+        let service = nets(&["192.0.2.0/24"]);
+
+        assert!(SourceRanges::default().allows(ip("198.51.100.1"), &service));
+
+        let ranges = SourceRanges { from_service: true, ..SourceRanges::default() };
+        assert!(ranges.allows(ip("192.0.2.1"), &service));
+        assert!(!ranges.allows(ip("198.51.100.1"), &service));
+    }
+
+    #[test]
+    fn mapped_ipv4_clients_match_ipv4_ranges() {
+        let ranges = SourceRanges { deny: nets(&["10.0.0.0/8"]), ..SourceRanges::default() };
+
+        assert!(!ranges.allows(ip("::ffff:10.0.0.1"), &[]));
+    }
+}
@@ -0,0 +1,8 @@
+named_enum! {
+    pub enum Strategy {
+        // Pick a random backend pod, so nodes are weighted by how many pods they host
+        Random => "random",
+        // Cycle through the backend pods in order
+        RoundRobin => "round-robin",
+    }
+}
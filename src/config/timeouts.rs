@@ -0,0 +1,34 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    #[serde(with = "humantime_serde")]
+    pub connect: Option<Duration>,
+    // How long to wait on one node address before also trying the next
+    #[serde(with = "humantime_serde")]
+    pub happy_eyeballs_delay: Option<Duration>,
+    // Closes connections that go this long without bytes in either direction
+    #[serde(with = "humantime_serde")]
+    pub idle: Option<Duration>,
+    // How long the other direction may go without bytes once one side has finished sending
+    #[serde(with = "humantime_serde")]
+    pub half_close: Option<Duration>,
+    // Closes connections this long after they were accepted, however busy they are
+    #[serde(with = "humantime_serde")]
+    pub max_lifetime: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Fills in anything not set on `self` from `fallback`
+    pub fn or(&self, fallback: &Timeouts) -> Timeouts {
+        Timeouts {
+            connect: self.connect.or(fallback.connect),
+            happy_eyeballs_delay: self.happy_eyeballs_delay.or(fallback.happy_eyeballs_delay),
+            idle: self.idle.or(fallback.idle),
+            half_close: self.half_close.or(fallback.half_close),
+            max_lifetime: self.max_lifetime.or(fallback.max_lifetime),
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use serde::Deserialize;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TopologyConfig {
+    // Prefer pods on nodes in the balancer's own zone, spilling over to every zone when it has too few
+    pub enabled: bool,
+    // The balancer's zone. Defaults to the topology.kubernetes.io/zone label of the node named by NODE_NAME.
+    pub zone: Option<String>,
+    // Share of all routable pods that must be in the zone for connections to stay there. At 0 they stay
+    // whenever the zone has any.
+    pub min_local_share: f64,
+}
+
+impl Default for TopologyConfig {
+    fn default() -> TopologyConfig {
+        TopologyConfig {
+            enabled: false,
+            zone: None,
+            min_local_share: 0.2,
+        }
+    }
+}
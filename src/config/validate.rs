@@ -0,0 +1,456 @@
+use crate::config::{Config, ConfigProblem, LimitsConfig, NodeAddressConfig, OverLimit, LogFormat, PortRef, Strategy, AddressFamily, DataPath, Timeouts};
+use crate::{Result, NodeBalancerError};
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
+
+impl Config {
+    /// Checks the whole config, returning every problem found rather than stopping at the first. With
+    /// custom_resource enabled the service and listeners may be left to the resource.
+    pub fn validate(&self) -> Result<()> {
+        self.check(self.custom_resource.enabled, Vec::new())
+    }
+
+    /// Checks the config once a NodeBalancer resource has been laid over it, reporting the problems found
+    /// applying the resource along with the config's own
+    pub fn validate_with_resource(&self, resource_problems: Vec<ConfigProblem>) -> Result<()> {
+        self.check(false, resource_problems)
+    }
+
+    fn check(&self, resource_pending: bool, mut problems: Vec<ConfigProblem>) -> Result<()> {
+        if self.service.name.is_empty() && !resource_pending {
+            problems.push(ConfigProblem::MissingServiceName);
+        }
+
+        if self.service.namespace.is_empty() {
+            problems.push(ConfigProblem::MissingServiceNamespace);
+        }
+
+        check_listen_addr(&mut problems, "the top-level config", &self.listen_addr);
+        check_strategy(&mut problems, "the top-level strategy", &self.strategy);
+        check_address_family(&mut problems, "the top-level address_family", &self.address_family);
+        check_data_path(&mut problems, "the top-level data_path", &self.data_path);
+        check_timeouts(&mut problems, "timeouts", &self.timeouts);
+
+        if self.listeners.is_empty() && !self.dynamic_listeners.enabled && !resource_pending {
+            problems.push(ConfigProblem::NoListeners);
+        }
+
+        let mut ports = HashSet::new();
+        for listener in &self.listeners {
+            if listener.port == 0 {
+                problems.push(ConfigProblem::ZeroListenerPort);
+            } else if !ports.insert(listener.port) && !problems.contains(&ConfigProblem::DuplicateListenerPort(listener.port)) {
+                problems.push(ConfigProblem::DuplicateListenerPort(listener.port));
+            }
+
+            match &listener.service_port {
+                Some(PortRef::Number(0)) => problems.push(ConfigProblem::MustBePositive(format!("service_port of listener {}", listener.port))),
+                Some(PortRef::Name(name)) if name.is_empty() => problems.push(ConfigProblem::EmptyPortName(listener.port)),
+                _ => {}
+            }
+
+            if let Some(listen_addr) = &listener.listen_addr {
+                check_listen_addr(&mut problems, &format!("listener {}", listener.port), listen_addr);
+            }
+
+            if let Some(strategy) = &listener.strategy {
+                check_strategy(&mut problems, &format!("listener {}", listener.port), strategy);
+            }
+
+            if let Some(address_family) = &listener.address_family {
+                check_address_family(&mut problems, &format!("listener {}", listener.port), address_family);
+            }
+
+            if let Some(data_path) = &listener.data_path {
+                check_data_path(&mut problems, &format!("listener {}", listener.port), data_path);
+            }
+
+            check_timeouts(&mut problems, &format!("timeouts of listener {}", listener.port), &listener.timeouts);
+
+            if listener.max_connections == Some(0) {
+                problems.push(ConfigProblem::MustBePositive(format!("max_connections of listener {}", listener.port)));
+            }
+        }
+
+        for (service_port, listen_port) in &self.dynamic_listeners.remap {
+            if service_port.is_empty() || service_port == "0" {
+                problems.push(ConfigProblem::InvalidRemapKey(service_port.clone()));
+            }
+
+            if *listen_port == 0 {
+                problems.push(ConfigProblem::ZeroRemapTarget(service_port.clone()));
+            }
+        }
+
+        if self.node_addresses.preference.is_empty() {
+            problems.push(ConfigProblem::NoAddressTypes);
+        }
+
+        for address_type in &self.node_addresses.preference {
+            if !NodeAddressConfig::ADDRESS_TYPES.contains(&address_type.as_str()) {
+                problems.push(ConfigProblem::UnknownAddressType(address_type.clone()));
+            }
+        }
+
+        check_limits(&mut problems, &self.limits);
+        check_positive(&mut problems, "drain.timeout", self.drain.timeout);
+        check_positive(&mut problems, "shutdown.timeout", self.shutdown.timeout);
+
+        if self.leader_election.enabled {
+            let leader_election = &self.leader_election;
+
+            if leader_election.lease_name.is_empty() {
+                problems.push(ConfigProblem::EmptyLeaseName);
+            }
+
+            // Leases are kept in whole seconds
+            if leader_election.lease_duration < Duration::from_secs(1) {
+                problems.push(ConfigProblem::MustBePositive("leader_election.lease_duration in whole seconds".to_owned()));
+            }
+
+            check_positive(&mut problems, "leader_election.retry_period", leader_election.retry_period);
+
+            if leader_election.renew_deadline >= leader_election.lease_duration {
+                problems.push(ConfigProblem::NotShorterThan("renew_deadline", "lease_duration"));
+            }
+
+            if leader_election.retry_period >= leader_election.renew_deadline {
+                problems.push(ConfigProblem::NotShorterThan("retry_period", "renew_deadline"));
+            }
+        }
+
+        if self.custom_resource.enabled && self.custom_resource.name.is_empty() {
+            problems.push(ConfigProblem::EmptyResourceName);
+        }
+
+        if self.service_status.enabled && self.service_status.ingress.is_empty() {
+            problems.push(ConfigProblem::NoIngress);
+        }
+
+        for ingress in &self.service_status.ingress {
+            if ingress.parse::<IpAddr>().is_err() && !is_hostname(ingress) {
+                problems.push(ConfigProblem::InvalidIngress(ingress.clone()));
+            }
+        }
+
+        check_positive(&mut problems, "events.interval", self.events.interval);
+
+        if self.node_weights.max_pods == Some(0) {
+            problems.push(ConfigProblem::MustBePositive("node_weights.max_pods".to_owned()));
+        }
+
+        if !(0.0..=1.0).contains(&self.topology.min_local_share) {
+            problems.push(ConfigProblem::InvalidLocalShare(self.topology.min_local_share));
+        }
+
+        if !(0.0..=1.0).contains(&self.access_log.sample_rate) {
+            problems.push(ConfigProblem::InvalidSampleRate(self.access_log.sample_rate));
+        }
+
+        if let LogFormat::Unknown(name) = &self.logging.format {
+            problems.push(ConfigProblem::UnknownLogFormat(name.clone()));
+        }
+
+        if let Err(e) = EnvFilter::try_new(&self.logging.filter) {
+            problems.push(ConfigProblem::InvalidLogFilter {
+                filter: self.logging.filter.clone(),
+                reason: e.to_string(),
+            });
+        }
+
+        if self.io_uring.enabled {
+            let io_uring = &self.io_uring;
+            let sizes = [
+                ("io_uring.workers", io_uring.workers),
+                ("io_uring.entries", io_uring.entries as usize),
+                ("io_uring.buffer_size", io_uring.buffer_size),
+            ];
+
+            for (name, size) in &sizes {
+                if *size == 0 {
+                    problems.push(ConfigProblem::MustBePositive((*name).to_owned()));
+                }
+            }
+        }
+
+        if self.otlp.enabled {
+            let endpoint = &self.otlp.endpoint;
+            let scheme_len = ["http://", "https://"].iter().find(|scheme| endpoint.starts_with(*scheme)).map(|scheme| scheme.len());
+            if scheme_len.is_none_or(|len| endpoint.len() == len) {
+                problems.push(ConfigProblem::InvalidOtlpEndpoint(endpoint.clone()));
+            }
+
+            check_positive(&mut problems, "otlp.export_interval", self.otlp.export_interval);
+            check_positive(&mut problems, "otlp.timeout", self.otlp.timeout);
+        }
+
+        if self.admin.enabled {
+            match self.admin.listen_addr.parse::<SocketAddr>() {
+                Ok(addr) => {
+                    // Ports derived with the offset depend on the service, those are skipped when listening
+                    let remapped = self.dynamic_listeners.enabled && self.dynamic_listeners.remap.values().any(|port| *port == addr.port());
+                    if remapped || self.listeners.iter().any(|listener| listener.port == addr.port()) {
+                        problems.push(ConfigProblem::AdminPortConflict(addr.port()));
+                    }
+                }
+                Err(_) => problems.push(ConfigProblem::InvalidAdminAddr(self.admin.listen_addr.clone())),
+            }
+        }
+
+        let health_check = &self.health_check;
+        check_positive(&mut problems, "health_check.interval", health_check.interval);
+        check_positive(&mut problems, "health_check.timeout", health_check.timeout);
+
+        if health_check.healthy_threshold == 0 {
+            problems.push(ConfigProblem::MustBePositive("health_check.healthy_threshold".to_owned()));
+        }
+
+        if health_check.unhealthy_threshold == 0 {
+            problems.push(ConfigProblem::MustBePositive("health_check.unhealthy_threshold".to_owned()));
+        }
+
+        let backoff = &self.backoff;
+        check_positive(&mut problems, "backoff.initial", backoff.initial);
+
+        // NaN and infinity would panic once turned into a delay
+        if !backoff.multiplier.is_finite() || backoff.multiplier < 1.0 {
+            problems.push(ConfigProblem::InvalidMultiplier(backoff.multiplier));
+        }
+
+        if !(0.0..=1.0).contains(&backoff.jitter) {
+            problems.push(ConfigProblem::InvalidJitter(backoff.jitter));
+        }
+
+        if backoff.initial > backoff.max {
+            problems.push(ConfigProblem::BackoffInitialAboveMax {
+                initial: backoff.initial,
+                max: backoff.max,
+            });
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            NodeBalancerError::InvalidConfig(problems).into()
+        }
+    }
+}
+
+fn check_listen_addr(problems: &mut Vec<ConfigProblem>, location: &str, addr: &str) {
+    if addr.parse::<IpAddr>().is_err() {
+        problems.push(ConfigProblem::InvalidListenAddr {
+            location: location.to_owned(),
+            addr: addr.to_owned(),
+        });
+    }
+}
+
+fn check_address_family(problems: &mut Vec<ConfigProblem>, location: &str, address_family: &AddressFamily) {
+    if let AddressFamily::Unknown(name) = address_family {
+        problems.push(ConfigProblem::UnknownAddressFamily {
+            location: location.to_owned(),
+            name: name.clone(),
+        });
+    }
+}
+
+fn check_data_path(problems: &mut Vec<ConfigProblem>, location: &str, data_path: &DataPath) {
+    if let DataPath::Unknown(name) = data_path {
+        problems.push(ConfigProblem::UnknownDataPath {
+            location: location.to_owned(),
+            name: name.clone(),
+        });
+    }
+}
+
+fn check_strategy(problems: &mut Vec<ConfigProblem>, location: &str, strategy: &Strategy) {
+    if let Strategy::Unknown(name) = strategy {
+        problems.push(ConfigProblem::UnknownStrategy {
+            location: location.to_owned(),
+            name: name.clone(),
+        });
+    }
+}
+
+fn check_timeouts(problems: &mut Vec<ConfigProblem>, location: &str, timeouts: &Timeouts) {
+    if let Some(connect) = timeouts.connect {
+        check_positive(problems, &format!("{}.connect", location), connect);
+    }
+
+    if let Some(delay) = timeouts.happy_eyeballs_delay {
+        check_positive(problems, &format!("{}.happy_eyeballs_delay", location), delay);
+    }
+
+    if let Some(idle) = timeouts.idle {
+        check_positive(problems, &format!("{}.idle", location), idle);
+    }
+
+    if let Some(half_close) = timeouts.half_close {
+        check_positive(problems, &format!("{}.half_close", location), half_close);
+    }
+
+    if let Some(max_lifetime) = timeouts.max_lifetime {
+        check_positive(problems, &format!("{}.max_lifetime", location), max_lifetime);
+    }
+}
+
+fn check_limits(problems: &mut Vec<ConfigProblem>, limits: &LimitsConfig) {
+    let maximums = [
+        ("limits.max_connections", limits.max_connections),
+        ("limits.max_connections_per_listener", limits.max_connections_per_listener),
+        ("limits.max_connections_per_ip", limits.max_connections_per_ip),
+    ];
+
+    for (name, max) in &maximums {
+        if *max == Some(0) {
+            problems.push(ConfigProblem::MustBePositive((*name).to_owned()));
+        }
+    }
+
+    if let Some(rate) = limits.rate_per_ip {
+        if !(rate.is_finite() && rate > 0.0) {
+            problems.push(ConfigProblem::InvalidRate(rate));
+        }
+    }
+
+    if limits.burst_per_ip == 0 {
+        problems.push(ConfigProblem::MustBePositive("limits.burst_per_ip".to_owned()));
+    }
+
+    match &limits.over_limit {
+        OverLimit::Reject => {}
+        OverLimit::Queue => {
+            check_positive(problems, "limits.queue_timeout", limits.queue_timeout);
+
+            if limits.queue_size == 0 {
+                problems.push(ConfigProblem::MustBePositive("limits.queue_size".to_owned()));
+            }
+        }
+        OverLimit::Unknown(name) => problems.push(ConfigProblem::UnknownOverLimit(name.clone())),
+    }
+}
+
+// DNS-1123 subdomain, which is what the API server accepts for status.loadBalancer.ingress hostnames
+fn is_hostname(name: &str) -> bool {
+    name.len() <= 253 && name.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && label.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+    })
+}
+
+fn check_positive(problems: &mut Vec<ConfigProblem>, name: &str, duration: Duration) {
+    if duration.is_zero() {
+        problems.push(ConfigProblem::MustBePositive(name.to_owned()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ListenerConfig;
+
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.service.name = "web".to_owned();
+        config.listeners = vec![ListenerConfig::new(8080)];
+        config
+    }
+
+    fn problems(config: &Config) -> Vec<ConfigProblem> {
+        match config.validate() {
+            Ok(()) => Vec::new(),
+            Err(NodeBalancerError::InvalidConfig(problems)) => problems,
+            Err(e) => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn default_with_service_and_listener_is_valid() {
+        assert_eq!(problems(&valid()), Vec::new());
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let mut config = valid();
+        config.service.name = String::new();
+        config.listen_addr = "not-an-ip".to_owned();
+        config.listeners.push(ListenerConfig::new(8080));
+        config.strategy = Strategy::Unknown("fastest".to_owned());
+
+        let problems = problems(&config);
+        assert!(problems.contains(&ConfigProblem::MissingServiceName));
+        assert!(problems.contains(&ConfigProblem::DuplicateListenerPort(8080)));
+        assert!(problems.contains(&ConfigProblem::InvalidListenAddr { location: "the top-level config".to_owned(), addr: "not-an-ip".to_owned() }));
+        assert!(problems.contains(&ConfigProblem::UnknownStrategy { location: "the top-level strategy".to_owned(), name: "fastest".to_owned() }));
+    }
+
+    #[test]
+    fn resource_may_supply_service_and_listeners() {
+        let mut config = Config::default();
+        config.custom_resource.enabled = true;
+
+        assert_eq!(problems(&config), Vec::new());
+        assert!(config.validate_with_resource(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn rejects_non_finite_backoff_multiplier() {
+        for multiplier in [f64::NAN, f64::INFINITY, 0.5] {
+            let mut config = valid();
+            config.backoff.multiplier = multiplier;
+
+            assert!(problems(&config).iter().any(|problem| matches!(problem, ConfigProblem::InvalidMultiplier(_))), "{}", multiplier);
+        }
+    }
+
+    #[test]
+    fn rejects_backoff_initial_above_max() {
+        let mut config = valid();
+        config.backoff.initial = Duration::from_secs(10);
+        config.backoff.max = Duration::from_secs(1);
+
+        assert!(problems(&config).iter().any(|problem| matches!(problem, ConfigProblem::BackoffInitialAboveMax { .. })));
+    }
+
+    #[test]
+    fn admin_port_conflicts_with_static_listener() {
+        let mut config = valid();
+        config.listeners.push(ListenerConfig::new(9090));
+
+        assert!(problems(&config).contains(&ConfigProblem::AdminPortConflict(9090)));
+    }
+
+    #[test]
+    fn admin_port_conflicts_with_remapped_dynamic_listener() {
+        let mut config = valid();
+        config.dynamic_listeners.enabled = true;
+        config.dynamic_listeners.remap.insert("http".to_owned(), 9090);
+
+        assert!(problems(&config).contains(&ConfigProblem::AdminPortConflict(9090)));
+
+        config.admin.enabled = false;
+        assert_eq!(problems(&config), Vec::new());
+    }
+
+    #[test]
+    fn leader_election_timings_must_nest() {
+        let mut config = valid();
+        config.leader_election.enabled = true;
+        config.leader_election.renew_deadline = config.leader_election.lease_duration;
+
+        assert!(problems(&config).contains(&ConfigProblem::NotShorterThan("renew_deadline", "lease_duration")));
+    }
+
+    #[test]
+    fn ingress_hostnames_follow_dns_1123() {
+        assert!(is_hostname("lb.example.com"));
+        assert!(!is_hostname("LB.example.com"));
+        assert!(!is_hostname("-lb.example.com"));
+        assert!(!is_hostname("lb..example.com"));
+    }
+}
@@ -0,0 +1,29 @@
+use std::fmt;
+
+/// Something connections are routed through, and that can be drained. Connections aren't tracked per pod: the
+/// balancer connects to a node port and kube-proxy on the node picks the pod, so the pod actually serving a
+/// connection isn't known here.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Backend {
+    Node(String),
+}
+
+impl Backend {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Backend::Node(_) => "node",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Backend::Node(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind(), self.name())
+    }
+}
@@ -0,0 +1,27 @@
+use crate::connections::ConnectionTracker;
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Keeps a connection registered with the tracker until dropped
+pub struct ConnectionGuard {
+    pub(super) id: u64,
+    pub(super) tracker: Arc<ConnectionTracker>,
+    pub(super) close: Arc<Notify>,
+}
+
+impl ConnectionGuard {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Resolves when the tracker wants the connection closed
+    pub async fn closed(&self) {
+        self.close.notified().await
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.tracker.deregister(self.id);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::config::LimitsConfig;
+use crate::connections::{ConnectionPermit, LimitExceeded};
+use crate::metrics::metrics;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+// How often buckets that have filled back up are forgotten
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Admits connections while they are within the configured limits. Limits are passed in on every call, so
+/// reloaded values apply to the next connection.
+pub struct ConnectionLimiter {
+    state: Mutex<LimiterState>,
+    // Notified whenever a permit is released
+    released: Notify,
+    queued: AtomicUsize,
+}
+
+struct LimiterState {
+    total: usize,
+    per_listener: HashMap<u16, usize>,
+    per_ip: HashMap<IpAddr, usize>,
+    buckets: HashMap<IpAddr, TokenBucket>,
+    last_prune: Instant,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, rate: f64, burst: f64) {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.updated).as_secs_f64() * rate).min(burst);
+        self.updated = now;
+    }
+}
+
+impl ConnectionLimiter {
+    pub fn new() -> ConnectionLimiter {
+        ConnectionLimiter {
+            state: Mutex::new(LimiterState {
+                total: 0,
+                per_listener: HashMap::new(),
+                per_ip: HashMap::new(),
+                buckets: HashMap::new(),
+                last_prune: Instant::now(),
+            }),
+            released: Notify::new(),
+            queued: AtomicUsize::new(0),
+        }
+    }
+
+    /// Admits the connection if it is within every limit, without waiting
+    pub fn try_acquire(self: &Arc<Self>, limits: &LimitsConfig, listen_port: u16, listener_max: Option<usize>, ip: IpAddr) -> Result<ConnectionPermit, LimitExceeded> {
+        let mut guard = self.state.lock();
+        let state = &mut *guard;
+
+        let listener_count = state.per_listener.get(&listen_port).copied().unwrap_or(0);
+        let ip_count = state.per_ip.get(&ip).copied().unwrap_or(0);
+
+        if limits.max_connections.is_some_and(|max| state.total >= max) {
+            return Err(LimitExceeded::Global);
+        }
+
+        if listener_max.is_some_and(|max| listener_count >= max) {
+            return Err(LimitExceeded::Listener);
+        }
+
+        if limits.max_connections_per_ip.is_some_and(|max| ip_count >= max) {
+            return Err(LimitExceeded::SourceIp);
+        }
+
+        // Tokens are only taken once every other limit passed, so a client isn't charged for rejected attempts
+        if let Some(rate) = limits.rate_per_ip {
+            let burst = limits.burst_per_ip as f64;
+            let now = Instant::now();
+
+            if state.last_prune.elapsed() >= PRUNE_INTERVAL {
+                state.buckets.retain(|_, bucket| {
+                    bucket.refill(rate, burst);
+                    bucket.tokens < burst
+                });
+                state.last_prune = now;
+            }
+
+            let bucket = state.buckets.entry(ip).or_insert(TokenBucket {
+                tokens: burst,
+                updated: now,
+            });
+            bucket.refill(rate, burst);
+
+            if bucket.tokens < 1.0 {
+                return Err(LimitExceeded::SourceRate(Duration::from_secs_f64((1.0 - bucket.tokens) / rate)));
+            }
+
+            bucket.tokens -= 1.0;
+        } else if !state.buckets.is_empty() {
+            state.buckets.clear();
+        }
+
+        state.total += 1;
+        *state.per_listener.entry(listen_port).or_default() += 1;
+        *state.per_ip.entry(ip).or_default() += 1;
+
+        Ok(ConnectionPermit {
+            limiter: Arc::clone(self),
+            listen_port,
+            ip,
+        })
+    }
+
+    /// Waits up to the queue timeout for the connection to fit within the limits
+    pub async fn acquire(self: &Arc<Self>, limits: &LimitsConfig, listen_port: u16, listener_max: Option<usize>, ip: IpAddr) -> Result<ConnectionPermit, LimitExceeded> {
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= limits.queue_size {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(LimitExceeded::QueueFull);
+        }
+
+        metrics().queued_connections.inc();
+        let deadline = Instant::now() + limits.queue_timeout;
+
+        let result = loop {
+            // Created before trying so a release in between isn't missed
+            let released = self.released.notified();
+
+            let exceeded = match self.try_acquire(limits, listen_port, listener_max, ip) {
+                Ok(permit) => break Ok(permit),
+                Err(e) => e,
+            };
+
+            let now = Instant::now();
+            if now >= deadline {
+                break Err(exceeded);
+            }
+
+            let remaining = deadline - now;
+            match exceeded {
+                // Releases don't refill buckets, only time does
+                LimitExceeded::SourceRate(wait) => tokio::time::sleep(wait.min(remaining)).await,
+                _ => {
+                    let _ = tokio::time::timeout(remaining, released).await;
+                }
+            }
+        };
+
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        metrics().queued_connections.dec();
+
+        result
+    }
+
+    pub(super) fn release(&self, listen_port: u16, ip: IpAddr) {
+        {
+            let mut state = self.state.lock();
+            state.total -= 1;
+            decrement(&mut state.per_listener, listen_port);
+            decrement(&mut state.per_ip, ip);
+        }
+
+        self.released.notify_waiters();
+    }
+}
+
+impl Default for ConnectionLimiter {
+    fn default() -> ConnectionLimiter {
+        ConnectionLimiter::new()
+    }
+}
+
+// Removes counts that reach zero so the maps only hold clients and listeners with open connections
+fn decrement<K: std::hash::Hash + Eq>(counts: &mut HashMap<K, usize>, key: K) {
+    if let Some(count) = counts.get_mut(&key) {
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+    const OTHER_CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 2));
+
+    fn rate_limited(rate: f64, burst: u32) -> LimitsConfig {
+        LimitsConfig {
+            rate_per_ip: Some(rate),
+            burst_per_ip: burst,
+            ..LimitsConfig::default()
+        }
+    }
+
+    #[test]
+    fn bucket_allows_a_burst_then_refuses() {
+        let limiter = Arc::new(ConnectionLimiter::new());
+        let limits = rate_limited(0.001, 3);
+
+        let permits: Vec<ConnectionPermit> = (0..3).map(|_| limiter.try_acquire(&limits, 80, None, CLIENT).unwrap()).collect();
+        assert!(matches!(limiter.try_acquire(&limits, 80, None, CLIENT), Err(LimitExceeded::SourceRate(_))));
+
+        // Closing connections doesn't give tokens back
+        drop(permits);
+        assert!(matches!(limiter.try_acquire(&limits, 80, None, CLIENT), Err(LimitExceeded::SourceRate(_))));
+    }
+
+    #[test]
+    fn buckets_are_per_client() {
+        let limiter = Arc::new(ConnectionLimiter::new());
+        let limits = rate_limited(0.001, 1);
+
+        let _first = limiter.try_acquire(&limits, 80, None, CLIENT).unwrap();
+        assert!(limiter.try_acquire(&limits, 80, None, CLIENT).is_err());
+        assert!(limiter.try_acquire(&limits, 80, None, OTHER_CLIENT).is_ok());
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let limiter = Arc::new(ConnectionLimiter::new());
+        let limits = rate_limited(1000.0, 1);
+
+        let _first = limiter.try_acquire(&limits, 80, None, CLIENT).unwrap();
+        let wait = match limiter.try_acquire(&limits, 80, None, CLIENT) {
+            Err(LimitExceeded::SourceRate(wait)) => wait,
+            other => panic!("expected a rate limit, got {:?}", other.map(|_| ())),
+        };
+
+        assert!(wait <= Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(limiter.try_acquire(&limits, 80, None, CLIENT).is_ok());
+    }
+
+    #[test]
+    fn rejected_attempts_cost_no_tokens() {
+        let limiter = Arc::new(ConnectionLimiter::new());
+        let limits = LimitsConfig {
+            max_connections_per_ip: Some(1),
+            ..rate_limited(0.001, 2)
+        };
+
+        let first = limiter.try_acquire(&limits, 80, None, CLIENT).unwrap();
+        assert_eq!(limiter.try_acquire(&limits, 80, None, CLIENT).err(), Some(LimitExceeded::SourceIp));
+
+        drop(first);
+        assert!(limiter.try_acquire(&limits, 80, None, CLIENT).is_ok());
+    }
+
+    #[test]
+    fn counts_are_released_with_permits() {
+        let limiter = Arc::new(ConnectionLimiter::new());
+        let limits = LimitsConfig {
+            max_connections: Some(1),
+            ..LimitsConfig::default()
+        };
+
+        let permit = limiter.try_acquire(&limits, 80, None, CLIENT).unwrap();
+        assert_eq!(limiter.try_acquire(&limits, 443, None, OTHER_CLIENT).err(), Some(LimitExceeded::Global));
+
+        drop(permit);
+        assert!(limiter.try_acquire(&limits, 443, None, OTHER_CLIENT).is_ok());
+    }
+}
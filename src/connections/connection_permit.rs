@@ -0,0 +1,16 @@
+use crate::connections::ConnectionLimiter;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Counts a connection against the limits until dropped
+pub struct ConnectionPermit {
+    pub(super) limiter: Arc<ConnectionLimiter>,
+    pub(super) listen_port: u16,
+    pub(super) ip: IpAddr,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.limiter.release(self.listen_port, self.ip);
+    }
+}
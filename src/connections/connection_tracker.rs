@@ -0,0 +1,275 @@
+use crate::connections::{Backend, ConnectionGuard};
+use crate::metrics::metrics;
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tracing::info;
+
+pub struct ConnectionTracker {
+    next_id: AtomicU64,
+    next_drain: AtomicU64,
+    connections: Mutex<Connections>,
+    draining: Mutex<HashMap<Backend, Drain>>,
+}
+
+struct Drain {
+    // Tells this drain's timeout apart from one started before it was cancelled
+    id: u64,
+    started: Instant,
+}
+
+#[derive(Default)]
+struct Connections {
+    // connection id -> connection
+    by_id: HashMap<u64, TrackedConnection>,
+    // backend -> ids of its connections, so draining one doesn't go through every connection
+    by_backend: HashMap<Backend, HashSet<u64>>,
+}
+
+struct TrackedConnection {
+    backend: Backend,
+    listener: String,
+    close: Arc<Notify>,
+}
+
+impl ConnectionTracker {
+    pub fn new() -> ConnectionTracker {
+        ConnectionTracker {
+            next_id: AtomicU64::new(0),
+            next_drain: AtomicU64::new(0),
+            connections: Mutex::new(Connections::default()),
+            draining: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn register(self: &Arc<Self>, node: &str, listen_port: u16) -> ConnectionGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let close = Arc::new(Notify::new());
+        let listener = listen_port.to_string();
+        let backend = Backend::Node(node.to_owned());
+
+        metrics().active_connections.with_label_values(&[&listener]).inc();
+
+        let mut connections = self.connections.lock();
+        connections.by_backend.entry(backend.clone()).or_default().insert(id);
+        connections.by_id.insert(id, TrackedConnection {
+            backend,
+            listener,
+            close: Arc::clone(&close),
+        });
+        drop(connections);
+
+        ConnectionGuard {
+            id,
+            tracker: Arc::clone(self),
+            close,
+        }
+    }
+
+    pub(super) fn deregister(&self, id: u64) {
+        let (connection, remaining) = {
+            let mut connections = self.connections.lock();
+            let connection = match connections.by_id.remove(&id) {
+                Some(v) => v,
+                None => return,
+            };
+
+            let remaining = match connections.by_backend.get_mut(&connection.backend) {
+                Some(ids) => {
+                    ids.remove(&id);
+                    ids.len()
+                }
+                None => 0,
+            };
+
+            if remaining == 0 {
+                connections.by_backend.remove(&connection.backend);
+            }
+
+            (connection, remaining)
+        };
+
+        metrics().active_connections.with_label_values(&[&connection.listener]).dec();
+
+        let backend = &connection.backend;
+        let mut draining = self.draining.lock();
+        if let Some(drain) = draining.get(backend) {
+            metrics().drain_remaining_connections.with_label_values(&[backend.kind(), backend.name()]).set(remaining as i64);
+
+            if remaining == 0 {
+                info!("Finished draining {} after {:?}", backend, drain.started.elapsed());
+                draining.remove(backend);
+                Self::drain_finished(backend);
+            }
+        }
+    }
+
+    pub fn active(&self) -> usize {
+        self.connections.lock().by_id.len()
+    }
+
+    pub fn is_draining(&self, backend: &Backend) -> bool {
+        self.draining.lock().contains_key(backend)
+    }
+
+    /// Lets existing connections through the backend finish, closing any still open after `timeout`. Callers
+    /// are responsible for no longer routing new connections to it.
+    pub fn drain(self: &Arc<Self>, backend: Backend, timeout: Duration) {
+        let remaining = self.count(&backend);
+        if remaining == 0 {
+            return;
+        }
+
+        let id = self.next_drain.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut draining = self.draining.lock();
+            if draining.contains_key(&backend) {
+                return;
+            }
+
+            draining.insert(backend.clone(), Drain { id, started: Instant::now() });
+        }
+
+        info!("Draining {} with {} open connections, closing any left after {:?}", backend, remaining, timeout);
+        metrics().draining_backends.with_label_values(&[backend.kind()]).inc();
+        metrics().drain_remaining_connections.with_label_values(&[backend.kind(), backend.name()]).set(remaining as i64);
+
+        let tracker = Arc::clone(self);
+        tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            tracker.finish_drain(&backend, id);
+        });
+    }
+
+    /// Stops draining a backend that came back, leaving its connections open
+    pub fn cancel_drain(&self, backend: &Backend) {
+        if self.draining.lock().remove(backend).is_some() {
+            info!("Cancelled draining {}, it is routable again", backend);
+            Self::drain_finished(backend);
+        }
+    }
+
+    /// Asks every open connection to close
+    pub fn close_all(&self) -> usize {
+        let connections = self.connections.lock();
+        connections.by_id.values().for_each(|connection| connection.close.notify_one());
+        connections.by_id.len()
+    }
+
+    fn finish_drain(&self, backend: &Backend, id: u64) {
+        {
+            let mut draining = self.draining.lock();
+            if draining.get(backend).is_none_or(|drain| drain.id != id) {
+                return; // Already drained or cancelled, and maybe draining again since
+            }
+
+            draining.remove(backend);
+        }
+
+        let closed = self.close_backend(backend);
+        info!("Drain timeout for {} reached, closing {} remaining connections", backend, closed);
+
+        metrics().drain_closed_connections.with_label_values(&[backend.kind()]).inc_by(closed as u64);
+        Self::drain_finished(backend);
+    }
+
+    fn close_backend(&self, backend: &Backend) -> usize {
+        let connections = self.connections.lock();
+        let ids = match connections.by_backend.get(backend) {
+            Some(ids) => ids,
+            None => return 0,
+        };
+
+        ids.iter()
+            .filter_map(|id| connections.by_id.get(id))
+            .map(|connection| connection.close.notify_one())
+            .count()
+    }
+
+    fn count(&self, backend: &Backend) -> usize {
+        self.connections.lock().by_backend.get(backend).map_or(0, HashSet::len)
+    }
+
+    fn drain_finished(backend: &Backend) {
+        metrics().draining_backends.with_label_values(&[backend.kind()]).dec();
+        let _ = metrics().drain_remaining_connections.remove_label_values(&[backend.kind(), backend.name()]);
+    }
+}
+
+impl Default for ConnectionTracker {
+    fn default() -> ConnectionTracker {
+        ConnectionTracker::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[tokio::test(start_paused = true)]
+    async fn drain_closes_only_the_nodes_connections_after_the_timeout() {
+        let tracker = Arc::new(ConnectionTracker::new());
+        let on_a = tracker.register("a", 80);
+        let on_b = tracker.register("b", 80);
+
+        tracker.drain(Backend::Node("a".to_owned()), Duration::from_secs(30));
+        assert!(tracker.is_draining(&Backend::Node("a".to_owned())));
+
+        tokio::time::sleep(Duration::from_secs(29)).await;
+        assert!(on_a.closed().now_or_never().is_none());
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(on_a.closed().now_or_never().is_some());
+        assert!(on_b.closed().now_or_never().is_none());
+        assert!(!tracker.is_draining(&Backend::Node("a".to_owned())));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelled_drain_does_not_cut_a_later_one_short() {
+        let tracker = Arc::new(ConnectionTracker::new());
+        let connection = tracker.register("a", 80);
+        let node = Backend::Node("a".to_owned());
+
+        tracker.drain(node.clone(), Duration::from_secs(30));
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        tracker.cancel_drain(&node);
+        tracker.drain(node.clone(), Duration::from_secs(30));
+
+        // Past the first drain's deadline
+        tokio::time::sleep(Duration::from_secs(25)).await;
+        assert!(connection.closed().now_or_never().is_none());
+        assert!(tracker.is_draining(&node));
+
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert!(connection.closed().now_or_never().is_some());
+    }
+
+    #[tokio::test]
+    async fn drain_finishes_when_the_last_connection_closes() {
+        let tracker = Arc::new(ConnectionTracker::new());
+        let first = tracker.register("a", 80);
+        let second = tracker.register("a", 443);
+        let node = Backend::Node("a".to_owned());
+
+        tracker.drain(node.clone(), Duration::from_secs(30));
+        drop(first);
+        assert!(tracker.is_draining(&node));
+
+        drop(second);
+        assert!(!tracker.is_draining(&node));
+        assert_eq!(tracker.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn draining_a_node_without_connections_does_nothing() {
+        let tracker = Arc::new(ConnectionTracker::new());
+        let _other = tracker.register("b", 80);
+
+        tracker.drain(Backend::Node("a".to_owned()), Duration::from_secs(30));
+        assert!(!tracker.is_draining(&Backend::Node("a".to_owned())));
+    }
+}
@@ -0,0 +1,38 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Why a connection was not admitted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Global,
+    Listener,
+    SourceIp,
+    // The client's bucket has a token again after the given time
+    SourceRate(Duration),
+    QueueFull,
+}
+
+impl LimitExceeded {
+    /// Label for the rejected connections metric
+    pub fn reason(&self) -> &'static str {
+        match self {
+            LimitExceeded::Global => "global",
+            LimitExceeded::Listener => "listener",
+            LimitExceeded::SourceIp => "source_ip",
+            LimitExceeded::SourceRate(_) => "source_rate",
+            LimitExceeded::QueueFull => "queue_full",
+        }
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitExceeded::Global => write!(f, "global connection limit reached"),
+            LimitExceeded::Listener => write!(f, "listener connection limit reached"),
+            LimitExceeded::SourceIp => write!(f, "connection limit for the client IP reached"),
+            LimitExceeded::SourceRate(_) => write!(f, "connection rate for the client IP exceeded"),
+            LimitExceeded::QueueFull => write!(f, "connection queue is full"),
+        }
+    }
+}
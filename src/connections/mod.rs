@@ -0,0 +1,17 @@
+mod connection_tracker;
+pub use connection_tracker::ConnectionTracker;
+
+mod connection_guard;
+pub use connection_guard::ConnectionGuard;
+
+mod connection_limiter;
+pub use connection_limiter::ConnectionLimiter;
+
+mod connection_permit;
+pub use connection_permit::ConnectionPermit;
+
+mod limit_exceeded;
+pub use limit_exceeded::LimitExceeded;
+
+mod backend;
+pub use backend::Backend;
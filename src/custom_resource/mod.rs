@@ -0,0 +1,14 @@
+mod node_balancer;
+pub use node_balancer::NodeBalancer;
+
+mod node_balancer_spec;
+pub use node_balancer_spec::{NodeBalancerSpec, ServiceSpec, ListenerSpec, HealthCheckSpec, SourceRangesSpec};
+
+mod node_balancer_status;
+pub use node_balancer_status::NodeBalancerStatus;
+
+mod resource_watcher;
+pub use resource_watcher::ResourceWatcher;
+
+mod resource_status;
+pub use resource_status::ResourceStatus;
@@ -0,0 +1,60 @@
+use crate::Config;
+use crate::config::ConfigProblem;
+use crate::custom_resource::{NodeBalancerSpec, NodeBalancerStatus};
+use kube::Resource;
+use kube::api::ObjectMeta;
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// The NodeBalancer custom resource (node-balancer.io/v1alpha1), configuring the balancer declaratively in
+/// place of env vars and most of the config file. The CRD is in deploy/crd.yaml.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct NodeBalancer {
+    pub metadata: ObjectMeta,
+    pub spec: NodeBalancerSpec,
+    pub status: Option<NodeBalancerStatus>,
+}
+
+impl NodeBalancer {
+    pub fn name(&self) -> &str {
+        self.metadata.name.as_deref().unwrap_or_default()
+    }
+
+    pub fn generation(&self) -> Option<i64> {
+        self.metadata.generation
+    }
+
+    /// Lays the spec over `config`, the service defaulting to the resource's namespace
+    pub fn apply(&self, config: &mut Config) -> Vec<ConfigProblem> {
+        let namespace = self.metadata.namespace.as_deref().unwrap_or("default");
+        self.spec.apply(namespace, config)
+    }
+}
+
+impl Resource for NodeBalancer {
+    type DynamicType = ();
+
+    fn kind(_: &()) -> Cow<'_, str> {
+        "NodeBalancer".into()
+    }
+
+    fn group(_: &()) -> Cow<'_, str> {
+        "node-balancer.io".into()
+    }
+
+    fn version(_: &()) -> Cow<'_, str> {
+        "v1alpha1".into()
+    }
+
+    fn plural(_: &()) -> Cow<'_, str> {
+        "nodebalancers".into()
+    }
+
+    fn meta(&self) -> &ObjectMeta {
+        &self.metadata
+    }
+
+    fn meta_mut(&mut self) -> &mut ObjectMeta {
+        &mut self.metadata
+    }
+}
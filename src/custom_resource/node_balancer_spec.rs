@@ -0,0 +1,177 @@
+use crate::config::{Config, ConfigProblem, ListenerConfig, PortRef, SourceRanges, Strategy};
+use serde::Deserialize;
+use std::time::Duration;
+
+// Field names follow Kubernetes conventions rather than the config file's. Unknown fields are left to the
+// CRD's schema, failing to parse here would stop the watch. For the same reason ranges and durations are
+// kept as strings and parsed when applied, so mistakes are reported in the status.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeBalancerSpec {
+    pub service: ServiceSpec,
+    // Replace the config file's listeners unless empty
+    #[serde(default)]
+    pub listeners: Vec<ListenerSpec>,
+    pub strategy: Option<Strategy>,
+    pub health_check: Option<HealthCheckSpec>,
+    pub source_ranges: Option<SourceRangesSpec>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ServiceSpec {
+    // Defaults to the resource's namespace
+    pub namespace: Option<String>,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListenerSpec {
+    pub port: u16,
+    pub listen_addr: Option<String>,
+    pub service_port: Option<PortRef>,
+    pub strategy: Option<Strategy>,
+    pub source_ranges: Option<SourceRangesSpec>,
+    pub max_connections: Option<usize>,
+}
+
+// Anything left out keeps the config file's value
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthCheckSpec {
+    pub enabled: Option<bool>,
+    pub interval: Option<String>,
+    pub timeout: Option<String>,
+    pub healthy_threshold: Option<u32>,
+    pub unhealthy_threshold: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SourceRangesSpec {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    pub from_service: bool,
+}
+
+impl NodeBalancerSpec {
+    /// Lays the spec over `config`, returning the fields that couldn't be parsed. Those keep the config
+    /// file's value.
+    pub fn apply(&self, namespace: &str, config: &mut Config) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+        config.service.namespace = self.service.namespace.clone().unwrap_or_else(|| namespace.to_owned());
+        config.service.name = self.service.name.clone();
+
+        if !self.listeners.is_empty() {
+            config.listeners = self.listeners.iter().enumerate()
+                .map(|(i, listener)| listener.to_config(&mut problems, &format!("spec.listeners[{}].sourceRanges", i)))
+                .collect();
+        }
+
+        if let Some(strategy) = &self.strategy {
+            config.strategy = strategy.clone();
+        }
+
+        if let Some(health_check) = &self.health_check {
+            let config = &mut config.health_check;
+            config.enabled = health_check.enabled.unwrap_or(true);
+            config.interval = parse_duration(&mut problems, "spec.healthCheck.interval", &health_check.interval).unwrap_or(config.interval);
+            config.timeout = parse_duration(&mut problems, "spec.healthCheck.timeout", &health_check.timeout).unwrap_or(config.timeout);
+            config.healthy_threshold = health_check.healthy_threshold.unwrap_or(config.healthy_threshold);
+            config.unhealthy_threshold = health_check.unhealthy_threshold.unwrap_or(config.unhealthy_threshold);
+        }
+
+        if let Some(source_ranges) = &self.source_ranges {
+            config.source_ranges = source_ranges.to_config(&mut problems, "spec.sourceRanges");
+        }
+
+        problems
+    }
+}
+
+impl ListenerSpec {
+    fn to_config(&self, problems: &mut Vec<ConfigProblem>, location: &str) -> ListenerConfig {
+        ListenerConfig {
+            listen_addr: self.listen_addr.clone(),
+            service_port: self.service_port.clone(),
+            strategy: self.strategy.clone(),
+            source_ranges: self.source_ranges.as_ref().map(|source_ranges| source_ranges.to_config(problems, location)),
+            max_connections: self.max_connections,
+            ..ListenerConfig::new(self.port)
+        }
+    }
+}
+
+impl SourceRangesSpec {
+    // Invalid entries are left out, and reported
+    fn to_config(&self, problems: &mut Vec<ConfigProblem>, location: &str) -> SourceRanges {
+        let mut parse = |field: &str, ranges: &[String]| ranges.iter()
+            .filter_map(|range| match range.trim().parse() {
+                Ok(net) => Some(net),
+                Err(_) => {
+                    problems.push(ConfigProblem::InvalidSourceRange {
+                        location: format!("{}.{}", location, field),
+                        range: range.clone(),
+                    });
+                    None
+                }
+            })
+            .collect();
+
+        SourceRanges {
+            allow: parse("allow", &self.allow),
+            deny: parse("deny", &self.deny),
+            from_service: self.from_service,
+        }
+    }
+}
+
+fn parse_duration(problems: &mut Vec<ConfigProblem>, location: &str, value: &Option<String>) -> Option<Duration> {
+    let value = value.as_ref()?;
+
+    match humantime::parse_duration(value.trim()) {
+        Ok(duration) => Some(duration),
+        Err(_) => {
+            problems.push(ConfigProblem::InvalidDuration {
+                location: location.to_owned(),
+                value: value.clone(),
+            });
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn spec(value: serde_json::Value) -> NodeBalancerSpec {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn reports_invalid_ranges_and_durations() {
+        let spec = spec(json!({
+            "service": { "name": "web" },
+            "listeners": [{ "port": 443, "sourceRanges": { "allow": ["10.0.0.0/8", "10.0.0.0/33"] } }],
+            "healthCheck": { "interval": "5s", "timeout": "soon" },
+            "sourceRanges": { "deny": ["192.168.0.0"] },
+        }));
+
+        let mut config = Config::default();
+        let timeout = config.health_check.timeout;
+        let problems = spec.apply("default", &mut config);
+
+        assert_eq!(problems, vec![
+            ConfigProblem::InvalidSourceRange { location: "spec.listeners[0].sourceRanges.allow".to_owned(), range: "10.0.0.0/33".to_owned() },
+            ConfigProblem::InvalidDuration { location: "spec.healthCheck.timeout".to_owned(), value: "soon".to_owned() },
+            ConfigProblem::InvalidSourceRange { location: "spec.sourceRanges.deny".to_owned(), range: "192.168.0.0".to_owned() },
+        ]);
+
+        assert_eq!(config.health_check.interval, Duration::from_secs(5));
+        assert_eq!(config.health_check.timeout, timeout);
+        assert_eq!(config.listeners[0].source_ranges.as_ref().unwrap().allow, vec!["10.0.0.0/8".parse().unwrap()]);
+        assert!(config.source_ranges.deny.is_empty());
+    }
+}
@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NodeBalancerStatus {
+    // The generation of the spec the balancer is running with
+    pub observed_generation: Option<i64>,
+    pub bound_ports: Vec<u16>,
+    // Pods connections can be routed to, and the nodes they run on
+    pub backend_pods: usize,
+    pub backend_nodes: usize,
+    // Why the spec couldn't be applied, or what stops the balancer from routing
+    pub errors: Vec<String>,
+}
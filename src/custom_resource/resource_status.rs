@@ -0,0 +1,134 @@
+use crate::{Result, NodeBalancerError};
+use crate::custom_resource::{NodeBalancer, NodeBalancerStatus, ResourceWatcher};
+use crate::leader_election::LeaderElector;
+use crate::proxy::Proxy;
+use kube::api::{Patch, PatchParams};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info_span, warn, Instrument};
+
+// Backend counts change without anything to wake up on, so the status is refreshed this often
+const RESYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+// Gives the proxy time to bind or close listeners after a change before reporting them
+const SETTLE_DELAY: Duration = Duration::from_secs(1);
+
+/// Writes the NodeBalancer resource's status subresource while this replica leads: the generation applied,
+/// bound ports, backend counts and anything stopping the balancer from routing
+pub struct ResourceStatus {
+    resource: Arc<ResourceWatcher>,
+    proxy: Arc<Proxy>,
+    leader_election: Arc<LeaderElector>,
+}
+
+impl ResourceStatus {
+    pub fn new(resource: Arc<ResourceWatcher>, proxy: Arc<Proxy>, leader_election: Arc<LeaderElector>) -> ResourceStatus {
+        ResourceStatus {
+            resource,
+            proxy,
+            leader_election,
+        }
+    }
+
+    pub fn start(self: &Arc<Self>) {
+        let status = Arc::clone(self);
+        tokio::spawn(async move { status.run().await }.instrument(info_span!("resource_status")));
+    }
+
+    async fn run(&self) {
+        let mut config = self.proxy.config.clone();
+        let mut resource = self.resource.subscribe();
+        let mut leading = self.leader_election.subscribe();
+        let mut port_map = self.proxy.router.watch_port_map();
+        let mut resync = tokio::time::interval(RESYNC_INTERVAL);
+
+        // What this replica last wrote, so an unchanged status isn't written again
+        let mut written: Option<NodeBalancerStatus> = None;
+
+        loop {
+            let current = resource.borrow().clone();
+            let is_leader = *leading.borrow();
+
+            match current {
+                Some(current) if is_leader => {
+                    let status = self.status(&current, written.as_ref());
+
+                    if written.as_ref() != Some(&status) {
+                        match self.write(&status).await {
+                            Ok(()) => {
+                                debug!("Wrote NodeBalancer status {:?}", status);
+                                written = Some(status);
+                            }
+                            Err(e) => warn!("Failed to write the status of NodeBalancer {}: {}", current.name(), e),
+                        }
+                    }
+                }
+                // The new leader writes its own
+                _ => written = None,
+            }
+
+            let changed = tokio::select! {
+                res = config.changed() => res.is_ok(),
+                res = resource.changed() => res.is_ok(),
+                res = leading.changed() => res.is_ok(),
+                res = port_map.changed() => res.is_ok(),
+                _ = resync.tick() => true,
+            };
+
+            if !changed {
+                return;
+            }
+
+            tokio::time::sleep(SETTLE_DELAY).await;
+        }
+    }
+
+    fn status(&self, resource: &NodeBalancer, written: Option<&NodeBalancerStatus>) -> NodeBalancerStatus {
+        let mut errors = Vec::new();
+        let config = self.proxy.config();
+
+        // The reloader applies the spec exactly when it validates, otherwise it keeps the previous config.
+        // Checked against the config in use rather than reloading the file and env vars on every resync.
+        let mut merged = (*config).clone();
+        let problems = resource.apply(&mut merged);
+        let observed_generation = match merged.validate_with_resource(problems) {
+            Ok(()) => resource.generation(),
+            Err(e) => {
+                errors.push(e.to_string());
+                written.and_then(|status| status.observed_generation)
+            }
+        };
+
+        let router = &self.proxy.router;
+
+        if !router.has_service() {
+            errors.push(format!("service {} was not found or is not of type NodePort or LoadBalancer", config.service));
+        }
+
+        let bound_ports = self.proxy.bound_ports();
+        for listener in &config.listeners {
+            if !bound_ports.contains(&listener.port) {
+                errors.push(format!("listener {} is not bound", listener.port));
+            }
+        }
+
+        let (backend_pods, backend_nodes) = router.backend_counts();
+
+        NodeBalancerStatus {
+            observed_generation,
+            bound_ports,
+            backend_pods,
+            backend_nodes,
+            errors,
+        }
+    }
+
+    async fn write(&self, status: &NodeBalancerStatus) -> Result<()> {
+        let patch = json!({ "status": status });
+        self.resource.api().patch_status(self.resource.name(), &PatchParams::default(), &Patch::Merge(&patch)).await
+            .map_err(NodeBalancerError::KubeError)?;
+
+        Ok(())
+    }
+}
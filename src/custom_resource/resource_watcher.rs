@@ -0,0 +1,113 @@
+use crate::{Config, Result, NodeBalancerError};
+use crate::backoff::{Backoff, retry};
+use crate::custom_resource::NodeBalancer;
+use futures_util::TryStreamExt;
+use kube::{Api, Client};
+use kube::api::ListParams;
+use kube_runtime::watcher;
+use kube_runtime::watcher::Event;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::{error, info, info_span, warn, Instrument};
+
+/// Follows the NodeBalancer resource named by custom_resource, publishing its spec for the config reloader
+pub struct ResourceWatcher {
+    api: Api<NodeBalancer>,
+    name: String,
+    namespace: String,
+    resource_tx: watch::Sender<Option<Arc<NodeBalancer>>>,
+}
+
+impl ResourceWatcher {
+    pub fn new(client: Client, config: &Config) -> ResourceWatcher {
+        let namespace = config.custom_resource.namespace.clone().unwrap_or_else(|| config.service.namespace.clone());
+
+        ResourceWatcher {
+            api: Api::namespaced(client, &namespace),
+            name: config.custom_resource.name.clone(),
+            namespace,
+            resource_tx: watch::channel(None).0,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(super) fn api(&self) -> &Api<NodeBalancer> {
+        &self.api
+    }
+
+    /// Follows the resource, None until it has been seeded
+    pub fn subscribe(&self) -> watch::Receiver<Option<Arc<NodeBalancer>>> {
+        self.resource_tx.subscribe()
+    }
+
+    /// Waits for the resource, returning `config` with it applied. Nothing can be balanced without it, so this
+    /// retries until the resource exists and applies cleanly.
+    pub async fn seed(&self, config: &Config) -> Config {
+        let what = format!("Fetching NodeBalancer {}/{}", self.namespace, self.name);
+
+        retry(&config.backoff, &what, || async {
+            let resource = self.api.get(&self.name).await.map_err(NodeBalancerError::KubeError)?;
+            let config = Config::load_with_resource(&resource)?;
+
+            info!("Applied NodeBalancer {}/{} generation {}", self.namespace, self.name, resource.generation().unwrap_or_default());
+            self.resource_tx.send_replace(Some(Arc::new(resource)));
+            Ok::<_, NodeBalancerError>(config)
+        }).instrument(info_span!("seed", phase = "resource")).await
+    }
+
+    /// Watches for changes to the resource, restarting the watch with backoff if it fails
+    pub fn start(self: &Arc<Self>, config: &Config) {
+        let resource = Arc::clone(self);
+        let policy = config.backoff.clone();
+
+        tokio::spawn(async move {
+            let mut backoff = Backoff::new(policy);
+
+            loop {
+                backoff.start();
+
+                if let Err(e) = resource.watch().await {
+                    error!("Error returned by NodeBalancer watcher: {}", e);
+                }
+
+                let delay = backoff.next_delay();
+                info!("Restarting NodeBalancer watcher in {:?}", delay);
+                tokio::time::sleep(delay).await;
+            }
+        }.instrument(info_span!("watch_resource", name = %self.name)));
+    }
+
+    async fn watch(&self) -> Result<()> {
+        let params = ListParams::default().fields(&format!("metadata.name={}", self.name));
+
+        watcher(self.api.clone(), params).try_for_each(|ev| async {
+            match ev {
+                Event::Applied(resource) => self.publish(resource),
+                Event::Restarted(resources) => resources.into_iter()
+                    .filter(|resource| resource.name() == self.name)
+                    .for_each(|resource| self.publish(resource)),
+                Event::Deleted(_) => {
+                    warn!("NodeBalancer {}/{} was deleted, carrying on with its last spec", self.namespace, self.name);
+                }
+            }
+
+            Ok(())
+        }).await.map_err(NodeBalancerError::WatcherError)?;
+
+        Ok(())
+    }
+
+    // Status updates come through here too, but only changes to the spec need the config reloading
+    fn publish(&self, resource: NodeBalancer) {
+        let changed = self.resource_tx.borrow().as_ref().is_none_or(|current| current.spec != resource.spec);
+        if !changed {
+            return;
+        }
+
+        info!("NodeBalancer {}/{} changed to generation {}", self.namespace, self.name, resource.generation().unwrap_or_default());
+        self.resource_tx.send_replace(Some(Arc::new(resource)));
+    }
+}
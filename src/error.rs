@@ -1,4 +1,5 @@
 use thiserror::Error;
+use crate::config::{ConfigProblem, PortRef};
 
 pub type Result<T> = std::result::Result<T, NodeBalancerError>;
 
@@ -7,6 +8,9 @@ pub enum NodeBalancerError {
     #[error("error operating on k8s: {0}")]
     KubeError(kube::Error),
 
+    #[error("failed to load kubeconfig: {0}")]
+    KubeConfigError(kube::Error),
+
     #[error("no pods available")]
     NoPodsAvailable,
 
@@ -16,11 +20,14 @@ pub enum NodeBalancerError {
     #[error("node {0} has no addresses")]
     NoAddressesAvailable(String),
 
+    #[error("failed to resolve {0}: {1}")]
+    DnsError(String, std::io::Error),
+
     #[error("service not found")]
     ServiceNotFound,
 
     #[error("port {0} not found")]
-    UnknownPort(u16),
+    UnknownPort(PortRef),
 
     #[error("kube watcher returned an error: {0}")]
     WatcherError(#[from] kube_runtime::watcher::Error),
@@ -28,15 +35,48 @@ pub enum NodeBalancerError {
     #[error("resource is missing spec")]
     MissingSpec,
 
-    #[error("service wanted NodePort, got {0}")]
+    #[error("service wanted NodePort or LoadBalancer, got {0}")]
     WrongServiceType(String),
 
     #[error("error occurred during IO operation: {0}")]
     IOError(std::io::Error),
+
+    #[error("timed out connecting to {0}")]
+    ConnectTimeout(String),
+
+    #[error("failed to read config file {}: {1}", .0.display())]
+    ConfigFileError(std::path::PathBuf, std::io::Error),
+
+    #[error("failed to parse config file: {0}")]
+    ConfigParseError(toml::de::Error),
+
+    #[error("failed to read config from env vars: {0}")]
+    EnvError(envy::Error),
+
+    #[error("failed to open access log {}: {1}", .0.display())]
+    AccessLogError(std::path::PathBuf, std::io::Error),
+
+    #[error("failed to set up logging: {0}")]
+    LoggingError(String),
+
+    #[error("failed to set up OTLP export: {0}")]
+    OtlpError(String),
+
+    #[error("failed to start admin server: {0}")]
+    AdminServerError(String),
+
+    #[error("invalid config:{}", format_problems(.0))]
+    InvalidConfig(Vec<ConfigProblem>),
+}
+
+fn format_problems(problems: &[ConfigProblem]) -> String {
+    problems.iter()
+        .map(|problem| format!("\n  - {}", problem))
+        .collect()
 }
 
-impl<T> Into<Result<T>> for NodeBalancerError {
-    fn into(self) -> Result<T> {
-        Err(self)
+impl<T> From<NodeBalancerError> for Result<T> {
+    fn from(e: NodeBalancerError) -> Result<T> {
+        Err(e)
     }
 }
@@ -0,0 +1,403 @@
+use crate::{Config, Result, NodeBalancerError};
+use crate::config::ServiceRef;
+use k8s_openapi::api::core::v1::{Event, EventSource, ObjectReference, Service};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::chrono::Utc;
+use kube::{Api, Client};
+use kube::api::{ObjectMeta, Patch, PatchParams, PostParams};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, watch};
+use tokio::time::Instant;
+use tracing::{debug, info_span, warn, Instrument};
+
+const COMPONENT: &str = "node-balancer";
+
+// Beyond this many occurrences waiting to be sent, more are dropped rather than holding up connections
+const QUEUE_SIZE: usize = 1024;
+
+// How often repeats held back by the interval are checked for
+const FLUSH_PERIOD: Duration = Duration::from_secs(10);
+
+// The API server deletes events an hour after their last update by default. They're forgotten a little
+// sooner so a repeat starts a new event rather than updating one about to go.
+const FORGET_AFTER: Duration = Duration::from_secs(50 * 60);
+
+/// Reports routing problems as Warning events on the Service or Node they concern, so they show up in
+/// `kubectl describe` and not only in the balancer's logs. Only the leader emits, and repeats of the same
+/// problem are counted into one event.
+pub struct EventRecorder {
+    config: watch::Receiver<Arc<Config>>,
+    tx: mpsc::Sender<Occurrence>,
+    leading: AtomicBool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum InvolvedObject {
+    Service(ServiceRef),
+    Node(String),
+}
+
+struct Occurrence {
+    object: InvolvedObject,
+    reason: &'static str,
+    message: String,
+}
+
+// Repeats are counted per object, reason and message. The messages name the port, so a problem on one port
+// doesn't hide the same problem on another.
+type Key = (InvolvedObject, &'static str, String);
+
+// An event for an object, reason and message
+struct Recorded {
+    namespace: String,
+    // None while creating the event keeps failing
+    name: Option<String>,
+    count: i32,
+    // Occurrences since the event was last created or updated
+    pending: i32,
+    // When the event was last created or updated, or that was last tried
+    updated: Instant,
+}
+
+// Owns the recorded events on the background task
+struct Emitter {
+    client: Client,
+    config: watch::Receiver<Arc<Config>>,
+    // HOSTNAME, which is the pod name in a cluster
+    instance: Option<String>,
+    recorded: HashMap<Key, Recorded>,
+}
+
+impl EventRecorder {
+    /// Starts the task that sends events, which runs until the recorder is dropped. Without a client there's
+    /// nowhere to send them, so they're discarded.
+    pub fn new(client: Option<Client>, config: watch::Receiver<Arc<Config>>) -> EventRecorder {
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+
+        if let Some(client) = client {
+            let emitter = Emitter {
+                client,
+                config: config.clone(),
+                instance: std::env::var("HOSTNAME").ok(),
+                recorded: HashMap::new(),
+            };
+            tokio::spawn(emitter.run(rx).instrument(info_span!("events")));
+        }
+
+        EventRecorder {
+            config,
+            tx,
+            leading: AtomicBool::new(false),
+        }
+    }
+
+    /// Follows leadership, events are only emitted while this replica leads
+    pub fn set_leading(&self, leading: bool) {
+        self.leading.store(leading, Ordering::Relaxed);
+    }
+
+    pub fn service_warning(&self, service: &ServiceRef, reason: &'static str, message: String) {
+        self.record(InvolvedObject::Service(service.clone()), reason, message);
+    }
+
+    pub fn node_warning(&self, node: &str, reason: &'static str, message: String) {
+        self.record(InvolvedObject::Node(node.to_owned()), reason, message);
+    }
+
+    // Never blocks, so it's safe to call from connection handling on any thread
+    fn record(&self, object: InvolvedObject, reason: &'static str, message: String) {
+        if !self.leading.load(Ordering::Relaxed) || !self.config.borrow().events.enabled {
+            return;
+        }
+
+        if self.tx.try_send(Occurrence { object, reason, message }).is_err() {
+            debug!("Dropped a {} event, too many are waiting to be sent", reason);
+        }
+    }
+}
+
+impl Emitter {
+    async fn run(mut self, mut rx: mpsc::Receiver<Occurrence>) {
+        let mut flush = tokio::time::interval(FLUSH_PERIOD);
+
+        loop {
+            tokio::select! {
+                occurrence = rx.recv() => match occurrence {
+                    Some(occurrence) => self.occurred(occurrence).await,
+                    None => return,
+                },
+                _ = flush.tick() => self.flush().await,
+            }
+        }
+    }
+
+    async fn occurred(&mut self, occurrence: Occurrence) {
+        let interval = self.config.borrow().events.interval;
+        let key = (occurrence.object, occurrence.reason, occurrence.message);
+
+        match self.recorded.get_mut(&key) {
+            Some(recorded) => {
+                recorded.pending += 1;
+
+                if recorded.updated.elapsed() >= interval {
+                    self.update(&key).await;
+                }
+            }
+            None => {
+                let recorded = match self.create(&key, 1).await {
+                    Ok(recorded) => recorded,
+                    // Kept with the occurrence pending, so creating it is retried once the interval passes rather
+                    // than on every repeat
+                    Err(e) => {
+                        warn!("Failed to create a {} event: {}", key.1, e);
+
+                        Recorded {
+                            namespace: key.0.namespace().to_owned(),
+                            name: None,
+                            count: 0,
+                            pending: 1,
+                            updated: Instant::now(),
+                        }
+                    }
+                };
+
+                self.recorded.insert(key, recorded);
+            }
+        }
+    }
+
+    async fn flush(&mut self) {
+        let interval = self.config.borrow().events.interval;
+
+        let due: Vec<_> = self.recorded.iter()
+            .filter(|(_, recorded)| recorded.pending > 0 && recorded.updated.elapsed() >= interval)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in due {
+            self.update(&key).await;
+        }
+
+        self.recorded.retain(|_, recorded| recorded.pending > 0 || recorded.updated.elapsed() < FORGET_AFTER);
+    }
+
+    // Adds the pending occurrences to the event's count, or creates it if that failed before. Failures are
+    // retried once the interval passes again.
+    async fn update(&mut self, key: &Key) {
+        let (namespace, name, count, pending) = match self.recorded.get(key) {
+            Some(recorded) => (recorded.namespace.clone(), recorded.name.clone(), recorded.count, recorded.pending),
+            None => return,
+        };
+
+        let patched = match &name {
+            Some(name) => self.patch(&namespace, name, count + pending).await,
+            None => Ok(false),
+        };
+
+        let result = match patched {
+            Ok(true) => Ok(None),
+            // Expired, deleted or never created, so start a new one
+            Ok(false) => self.create(key, pending).await.map(Some),
+            Err(e) => Err(e),
+        };
+
+        if let Some(recorded) = self.recorded.get_mut(key) {
+            recorded.updated = Instant::now();
+
+            match result {
+                Ok(Some(created)) => *recorded = created,
+                Ok(None) => {
+                    recorded.count = count + pending;
+                    recorded.pending = 0;
+                }
+                Err(e) => warn!("Failed to update the {} event: {}", key.1, e),
+            }
+        }
+    }
+
+    // Returns false if the event no longer exists
+    async fn patch(&self, namespace: &str, name: &str, count: i32) -> Result<bool> {
+        let patch = json!({
+            "count": count,
+            "lastTimestamp": Time(Utc::now()),
+        });
+
+        let api: Api<Event> = Api::namespaced(self.client.clone(), namespace);
+        match api.patch(name, &PatchParams::default(), &Patch::Merge(&patch)).await {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(e)) if e.code == 404 => Ok(false),
+            Err(e) => NodeBalancerError::KubeError(e).into(),
+        }
+    }
+
+    async fn create(&self, (object, reason, message): &Key, count: i32) -> Result<Recorded> {
+        let namespace = object.namespace();
+        let now = Time(Utc::now());
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+
+        let event = Event {
+            metadata: ObjectMeta {
+                // The same naming kubectl and client-go use
+                name: Some(format!("{}.{:x}", object.name(), nanos)),
+                namespace: Some(namespace.to_owned()),
+                ..ObjectMeta::default()
+            },
+            involved_object: self.reference(object).await,
+            reason: Some((*reason).to_owned()),
+            message: Some(message.clone()),
+            type_: Some("Warning".to_owned()),
+            count: Some(count),
+            first_timestamp: Some(now.clone()),
+            last_timestamp: Some(now),
+            source: Some(EventSource {
+                component: Some(COMPONENT.to_owned()),
+                host: None,
+            }),
+            reporting_component: Some(COMPONENT.to_owned()),
+            reporting_instance: self.instance.clone(),
+            ..Event::default()
+        };
+
+        let api: Api<Event> = Api::namespaced(self.client.clone(), namespace);
+        let event = api.create(&PostParams::default(), &event).await.map_err(NodeBalancerError::KubeError)?;
+
+        Ok(Recorded {
+            namespace: namespace.to_owned(),
+            name: event.metadata.name,
+            count,
+            pending: 0,
+            updated: Instant::now(),
+        })
+    }
+
+    // kubectl describe matches events by the object's uid
+    async fn reference(&self, object: &InvolvedObject) -> ObjectReference {
+        match object {
+            InvolvedObject::Service(service) => {
+                let api: Api<Service> = Api::namespaced(self.client.clone(), &service.namespace);
+                // Left without a uid if the service doesn't exist, which is what the event may be about
+                let uid = api.get(&service.name).await.ok().and_then(|svc| svc.metadata.uid);
+
+                ObjectReference {
+                    api_version: Some("v1".to_owned()),
+                    kind: Some("Service".to_owned()),
+                    namespace: Some(service.namespace.clone()),
+                    name: Some(service.name.clone()),
+                    uid,
+                    ..ObjectReference::default()
+                }
+            }
+            // Like the kubelet, which kubectl describe node expects, this uses the node's name as its uid
+            InvolvedObject::Node(node) => ObjectReference {
+                api_version: Some("v1".to_owned()),
+                kind: Some("Node".to_owned()),
+                name: Some(node.clone()),
+                uid: Some(node.clone()),
+                ..ObjectReference::default()
+            },
+        }
+    }
+}
+
+impl InvolvedObject {
+    fn name(&self) -> &str {
+        match self {
+            InvolvedObject::Service(service) => &service.name,
+            InvolvedObject::Node(node) => node,
+        }
+    }
+
+    // Nodes aren't namespaced, their events go in default like the kubelet's
+    fn namespace(&self) -> &str {
+        match self {
+            InvolvedObject::Service(service) => &service.namespace,
+            InvolvedObject::Node(_) => "default",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{Body, Method, Request, Response, Server, StatusCode};
+    use hyper::service::{make_service_fn, service_fn};
+    use std::convert::{Infallible, TryFrom};
+    use std::sync::atomic::AtomicUsize;
+
+    // An API server that fails every request, counting the attempts to create events
+    fn failing_client(creates: Arc<AtomicUsize>) -> Client {
+        let make_service = make_service_fn(move |_| {
+            let creates = Arc::clone(&creates);
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    if req.method() == Method::POST && req.uri().path().ends_with("/events") {
+                        creates.fetch_add(1, Ordering::Relaxed);
+                    }
+
+                    async {
+                        Ok::<_, Infallible>(Response::builder()
+                            .status(StatusCode::INTERNAL_SERVER_ERROR)
+                            .body(Body::empty())
+                            .unwrap())
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let url = format!("http://{}", server.local_addr()).parse().unwrap();
+        tokio::spawn(server);
+
+        Client::try_from(kube::Config::new(url)).unwrap()
+    }
+
+    fn emitter(client: Client) -> Emitter {
+        let mut config = Config::default();
+        config.events.interval = Duration::from_secs(3600);
+
+        Emitter {
+            client,
+            config: watch::channel(Arc::new(config)).1,
+            instance: None,
+            recorded: HashMap::new(),
+        }
+    }
+
+    fn no_backends(port: u16) -> Occurrence {
+        Occurrence {
+            object: InvolvedObject::Service(ServiceRef { namespace: "default".to_owned(), name: "web".to_owned() }),
+            reason: "NoRoutableBackends",
+            message: format!("no routable backends for port {}", port),
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_creates_wait_for_the_interval() {
+        let creates = Arc::new(AtomicUsize::new(0));
+        let mut emitter = emitter(failing_client(Arc::clone(&creates)));
+
+        for _ in 0..3 {
+            emitter.occurred(no_backends(80)).await;
+        }
+
+        assert_eq!(creates.load(Ordering::Relaxed), 1);
+        assert_eq!(emitter.recorded.values().map(|recorded| recorded.pending).sum::<i32>(), 3);
+    }
+
+    #[tokio::test]
+    async fn ports_get_their_own_events() {
+        let creates = Arc::new(AtomicUsize::new(0));
+        let mut emitter = emitter(failing_client(Arc::clone(&creates)));
+
+        emitter.occurred(no_backends(80)).await;
+        emitter.occurred(no_backends(443)).await;
+
+        assert_eq!(creates.load(Ordering::Relaxed), 2);
+        assert_eq!(emitter.recorded.len(), 2);
+    }
+}
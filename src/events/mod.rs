@@ -0,0 +1,2 @@
+mod event_recorder;
+pub use event_recorder::EventRecorder;
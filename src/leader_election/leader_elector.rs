@@ -0,0 +1,308 @@
+use crate::{Config, Result, NodeBalancerError};
+use crate::config::LeaderElectionConfig;
+use crate::metrics::metrics;
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+use k8s_openapi::chrono::Utc;
+use kube::{Api, Client};
+use kube::api::{ObjectMeta, PostParams};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{info, info_span, warn, Instrument};
+
+type Callback = Box<dyn Fn() + Send + Sync>;
+
+/// Elects one replica to perform cluster-mutating duties by holding a coordination.k8s.io/v1 Lease. Every
+/// replica keeps proxying whether it leads or not.
+pub struct LeaderElector {
+    api: Api<Lease>,
+    config: LeaderElectionConfig,
+    identity: String,
+    leading_tx: watch::Sender<bool>,
+    on_started: Mutex<Vec<Callback>>,
+    on_stopped: Mutex<Vec<Callback>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+// The lease as last seen and when. Expiry is judged by our own clock from when the lease last changed, so
+// clock skew between replicas doesn't matter.
+struct Observed {
+    spec: LeaseSpec,
+    at: Instant,
+}
+
+impl LeaderElector {
+    pub fn new(client: Client, config: &Config) -> LeaderElector {
+        let election = config.leader_election.clone();
+        let namespace = election.lease_namespace.as_deref().unwrap_or(&config.service.namespace);
+
+        let identity = election.identity.clone()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .unwrap_or_else(|| format!("node-balancer-{:08x}", rand::random::<u32>()));
+
+        LeaderElector {
+            api: Api::namespaced(client, namespace),
+            config: election,
+            identity,
+            leading_tx: watch::channel(false).0,
+            on_started: Mutex::new(Vec::new()),
+            on_stopped: Mutex::new(Vec::new()),
+            task: Mutex::new(None),
+        }
+    }
+
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    pub fn is_leader(&self) -> bool {
+        *self.leading_tx.borrow()
+    }
+
+    /// Follows leadership, true while this replica leads
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.leading_tx.subscribe()
+    }
+
+    /// Runs `callback` each time this replica becomes the leader. Callbacks run on the election task, so
+    /// anything slow should be spawned.
+    pub fn on_started_leading<F: Fn() + Send + Sync + 'static>(&self, callback: F) {
+        self.on_started.lock().push(Box::new(callback));
+    }
+
+    /// Runs `callback` each time this replica stops leading, including on release
+    pub fn on_stopped_leading<F: Fn() + Send + Sync + 'static>(&self, callback: F) {
+        self.on_stopped.lock().push(Box::new(callback));
+    }
+
+    /// Starts competing for the lease, or leads straight away if leader election is disabled
+    pub fn start(self: &Arc<Self>) {
+        if !self.config.enabled {
+            self.set_leading(true);
+            return;
+        }
+
+        info!("Competing for lease {} as {}", self.config.lease_name, self.identity);
+
+        let elector = Arc::clone(self);
+        let span = info_span!("leader_election", lease = %self.config.lease_name);
+        *self.task.lock() = Some(tokio::spawn(async move { elector.run().await }.instrument(span)));
+    }
+
+    /// Stops competing and, if leading, gives the lease up so another replica can take over without waiting
+    /// for it to expire
+    pub async fn release(&self) {
+        if let Some(task) = self.task.lock().take() {
+            task.abort();
+        }
+
+        if !self.is_leader() {
+            return;
+        }
+
+        if self.config.enabled {
+            match self.give_up().await {
+                Ok(()) => info!("Released lease {}", self.config.lease_name),
+                Err(e) => warn!("Failed to release lease {}, it will expire instead: {}", self.config.lease_name, e),
+            }
+        }
+
+        self.set_leading(false);
+    }
+
+    async fn run(&self) {
+        let mut observed = None;
+        let mut last_renewed = Instant::now();
+
+        loop {
+            // The leader only has what's left of the renew deadline, so a hung request can't keep it leading
+            // past the point another replica may take over
+            let deadline = if self.is_leader() {
+                self.config.renew_deadline.saturating_sub(last_renewed.elapsed())
+            } else {
+                self.config.renew_deadline
+            };
+
+            match tokio::time::timeout(deadline, self.try_acquire_or_renew(&mut observed)).await {
+                Ok(Ok(true)) => {
+                    last_renewed = Instant::now();
+                    self.set_leading(true);
+                }
+                Ok(Ok(false)) => self.set_leading(false),
+                Ok(Err(e)) => {
+                    warn!("Failed to acquire or renew lease {}: {}", self.config.lease_name, e);
+
+                    if self.is_leader() && last_renewed.elapsed() >= self.config.renew_deadline {
+                        warn!("Couldn't renew lease {} within {:?}", self.config.lease_name, self.config.renew_deadline);
+                        self.set_leading(false);
+                    }
+                }
+                Err(_) => {
+                    warn!("Timed out acquiring or renewing lease {} after {:?}", self.config.lease_name, deadline);
+
+                    if self.is_leader() {
+                        warn!("Couldn't renew lease {} within {:?}", self.config.lease_name, self.config.renew_deadline);
+                        self.set_leading(false);
+                    }
+                }
+            }
+
+            tokio::time::sleep(self.config.retry_period).await;
+        }
+    }
+
+    // Returns whether this replica holds the lease afterwards
+    async fn try_acquire_or_renew(&self, observed: &mut Option<Observed>) -> Result<bool> {
+        let name = &self.config.lease_name;
+
+        let mut lease = match self.api.get(name).await {
+            Ok(lease) => lease,
+            Err(kube::Error::Api(e)) if e.code == 404 => return self.create().await,
+            Err(e) => return NodeBalancerError::KubeError(e).into(),
+        };
+
+        let spec = lease.spec.clone().unwrap_or_default();
+        if observed.as_ref().map(|observed| &observed.spec) != Some(&spec) {
+            *observed = Some(Observed { spec: spec.clone(), at: Instant::now() });
+        }
+
+        let holder = spec.holder_identity.as_deref().unwrap_or("");
+        let held = holder == self.identity;
+
+        if !held && !holder.is_empty() {
+            let duration = Duration::from_secs(spec.lease_duration_seconds.unwrap_or(0).max(0) as u64);
+            if observed.as_ref().is_some_and(|observed| observed.at.elapsed() < duration) {
+                return Ok(false);
+            }
+
+            info!("Lease {} held by {} has expired, taking over", name, holder);
+        }
+
+        let now = MicroTime(Utc::now());
+        let transitions = spec.lease_transitions.unwrap_or(0);
+        lease.spec = Some(LeaseSpec {
+            holder_identity: Some(self.identity.clone()),
+            lease_duration_seconds: Some(self.lease_duration_seconds()),
+            acquire_time: if held { spec.acquire_time } else { Some(now.clone()) },
+            renew_time: Some(now),
+            lease_transitions: Some(if held { transitions } else { transitions + 1 }),
+        });
+
+        // Sent with the resource version we read, so if another replica got there first this conflicts
+        match self.api.replace(name, &PostParams::default(), &lease).await {
+            Ok(lease) => {
+                *observed = Some(Observed { spec: lease.spec.unwrap_or_default(), at: Instant::now() });
+                Ok(true)
+            }
+            Err(kube::Error::Api(e)) if e.code == 409 => Ok(false),
+            Err(e) => NodeBalancerError::KubeError(e).into(),
+        }
+    }
+
+    async fn create(&self) -> Result<bool> {
+        let now = MicroTime(Utc::now());
+        let lease = Lease {
+            metadata: ObjectMeta {
+                name: Some(self.config.lease_name.clone()),
+                ..ObjectMeta::default()
+            },
+            spec: Some(LeaseSpec {
+                holder_identity: Some(self.identity.clone()),
+                lease_duration_seconds: Some(self.lease_duration_seconds()),
+                acquire_time: Some(now.clone()),
+                renew_time: Some(now),
+                lease_transitions: Some(0),
+            }),
+        };
+
+        match self.api.create(&PostParams::default(), &lease).await {
+            Ok(_) => Ok(true),
+            // Another replica created it first
+            Err(kube::Error::Api(e)) if e.code == 409 => Ok(false),
+            Err(e) => NodeBalancerError::KubeError(e).into(),
+        }
+    }
+
+    // Clears the holder and shortens the lease, the same as client-go does on release
+    async fn give_up(&self) -> Result<()> {
+        let name = &self.config.lease_name;
+        let mut lease = self.api.get(name).await.map_err(NodeBalancerError::KubeError)?;
+
+        let spec = lease.spec.get_or_insert_with(LeaseSpec::default);
+        if spec.holder_identity.as_deref() != Some(&self.identity) {
+            return Ok(());
+        }
+
+        spec.holder_identity = None;
+        spec.lease_duration_seconds = Some(1);
+        spec.renew_time = Some(MicroTime(Utc::now()));
+
+        self.api.replace(name, &PostParams::default(), &lease).await.map_err(NodeBalancerError::KubeError)?;
+        Ok(())
+    }
+
+    fn set_leading(&self, leading: bool) {
+        if *self.leading_tx.borrow() == leading {
+            return;
+        }
+
+        self.leading_tx.send_replace(leading);
+        metrics().leader.set(leading as i64);
+
+        if leading {
+            info!("Became the leader as {}", self.identity);
+            self.on_started.lock().iter().for_each(|callback| callback());
+        } else {
+            info!("No longer the leader");
+            self.on_stopped.lock().iter().for_each(|callback| callback());
+        }
+    }
+
+    fn lease_duration_seconds(&self) -> i32 {
+        self.config.lease_duration.as_secs().min(i32::MAX as u64) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use tokio::net::TcpListener;
+
+    // An API server that accepts connections and never answers
+    async fn unresponsive_client() -> Client {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap()).parse().unwrap();
+
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+
+        Client::try_from(kube::Config::new(url)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn steps_down_when_renewal_hangs_past_deadline() {
+        let mut config = Config::default();
+        config.leader_election.enabled = true;
+        config.leader_election.renew_deadline = Duration::from_millis(100);
+        config.leader_election.retry_period = Duration::from_millis(10);
+
+        let elector = Arc::new(LeaderElector::new(unresponsive_client().await, &config));
+        elector.set_leading(true);
+        elector.start();
+
+        let mut leading = elector.subscribe();
+        tokio::time::timeout(Duration::from_secs(1), leading.wait_for(|leading| !leading))
+            .await
+            .expect("still leading after the renew deadline")
+            .unwrap();
+    }
+}
@@ -0,0 +1,2 @@
+mod leader_elector;
+pub use leader_elector::LeaderElector;
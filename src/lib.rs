@@ -1,8 +1,22 @@
 mod error;
 pub use error::{NodeBalancerError, Result};
 
-mod config;
+pub mod config;
 pub use config::Config;
 
+pub mod backoff;
+pub mod metrics;
+pub mod logging;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+pub mod access_log;
+pub mod admin;
+pub mod connections;
+pub mod leader_election;
+pub mod service_status;
+pub mod events;
+pub mod custom_resource;
+pub mod shutdown;
+pub mod cluster_source;
 pub mod router;
-pub mod proxy;
\ No newline at end of file
+pub mod proxy;
@@ -0,0 +1,96 @@
+use crate::{Config, Result, NodeBalancerError};
+use crate::config::{LogFormat, LoggingConfig};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+use tracing_subscriber::{fmt, reload, EnvFilter, Layer, Registry};
+use tracing_subscriber::layer::Layered;
+use tracing_subscriber::prelude::*;
+
+/// What exporting layers are stacked on, they see the same spans and events as the log output
+pub type FilteredRegistry = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+pub type ExportLayer = Box<dyn Layer<FilteredRegistry> + Send + Sync>;
+
+/// The filter deciding which spans and events are logged, which can be replaced while running
+pub struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    // As given, EnvFilter doesn't keep the original text
+    directives: Mutex<String>,
+}
+
+/// Installs the global subscriber, writing to stderr in the configured format and passing spans to `exporters`.
+/// Events from crates still using `log` are picked up too.
+pub fn init(config: &LoggingConfig, exporters: Vec<ExportLayer>) -> Result<LogFilter> {
+    let env = std::env::var("RUST_LOG").ok().filter(|directives| !directives.is_empty());
+
+    // A bad RUST_LOG only gets a warning once logging is up, config filters have already been validated
+    let (directives, filter, env_error) = match env.as_deref().map(EnvFilter::try_new) {
+        Some(Ok(filter)) => (env.unwrap(), filter, None),
+        Some(Err(e)) => (config.filter.clone(), EnvFilter::new(&config.filter), Some(e)),
+        None => (config.filter.clone(), EnvFilter::new(&config.filter), None),
+    };
+
+    let (filter, handle) = reload::Layer::new(filter);
+    // An empty Vec of layers is never interested in anything and would silence the log output too
+    let exporters = Some(exporters).filter(|exporters| !exporters.is_empty());
+    let registry = tracing_subscriber::registry().with(filter).with(exporters);
+
+    let res = match config.format {
+        LogFormat::Json => registry
+            .with(fmt::layer().json().with_current_span(true).with_span_list(true).with_writer(std::io::stderr))
+            .try_init(),
+        _ => registry
+            .with(fmt::layer().with_writer(std::io::stderr))
+            .try_init(),
+    };
+
+    res.map_err(|e| NodeBalancerError::LoggingError(e.to_string()))?;
+
+    if let Some(e) = env_error {
+        warn!("Ignoring RUST_LOG, it is invalid: {}", e);
+    }
+
+    Ok(LogFilter {
+        handle,
+        directives: Mutex::new(directives),
+    })
+}
+
+impl LogFilter {
+    pub fn directives(&self) -> String {
+        self.directives.lock().clone()
+    }
+
+    /// Replaces the filter, leaving the current one in place if `directives` don't parse
+    pub fn set(&self, directives: &str) -> Result<()> {
+        let filter = EnvFilter::try_new(directives)
+            .map_err(|e| NodeBalancerError::LoggingError(format!("invalid filter {:?}: {}", directives, e)))?;
+
+        self.handle.reload(filter).map_err(|e| NodeBalancerError::LoggingError(e.to_string()))?;
+        *self.directives.lock() = directives.to_owned();
+
+        info!("Log filter set to {:?}", directives);
+        Ok(())
+    }
+
+    /// Applies logging.filter whenever a reload changes it
+    pub fn watch(self: Arc<Self>, mut config: watch::Receiver<Arc<Config>>) {
+        tokio::spawn(async move {
+            let mut applied = config.borrow().logging.filter.clone();
+
+            while config.changed().await.is_ok() {
+                let wanted = config.borrow().logging.filter.clone();
+                if wanted == applied {
+                    continue;
+                }
+
+                if let Err(e) = self.set(&wanted) {
+                    error!("Failed to apply logging.filter: {}", e);
+                }
+
+                applied = wanted;
+            }
+        });
+    }
+}
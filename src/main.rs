@@ -1,21 +1,194 @@
 use node_balancer::router::Router;
+use node_balancer::cluster_source::KubernetesSource;
+use node_balancer::proxy::{self, Proxy};
+use node_balancer::config::reload;
+use node_balancer::admin::{self, Readiness};
+use node_balancer::shutdown::{self, ShutdownSignals};
+use node_balancer::leader_election::LeaderElector;
+use node_balancer::service_status::ServiceStatus;
+use node_balancer::custom_resource::{ResourceStatus, ResourceWatcher};
+use node_balancer::access_log::AccessLog;
+use node_balancer::logging;
+#[cfg(feature = "otlp")]
+use node_balancer::otlp::Otlp;
+use std::future::Future;
 use std::sync::Arc;
-use node_balancer::Config;
-
-// IMPORTANT
-// SET externalTrafficPolicy on service to cluster not local
-// not anymore ignore this
-// TODO: EXPO BACKOFF WHEN MINIKUBE IS DOWN
+use node_balancer::{Config, NodeBalancerError};
+use node_balancer::config::{DataPath, LoggingConfig};
+use tokio::sync::watch;
+use tracing::{error, info};
 
 #[tokio::main]
 async fn main() {
-    env_logger::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("bench") => bench(&args[1..]).await,
+        _ => run().await,
+    };
+
+    if let Err(e) = result {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run() -> node_balancer::Result<()> {
+    let mut signals = ShutdownSignals::register()?;
+
+    // Logging is set up before the config is checked so problems with it are reported, falling back to the
+    // defaults if it can't be loaded
+    let config = Config::load();
+
+    // Started before logging so the exporter sees every span
+    #[cfg(feature = "otlp")]
+    let otlp = match &config {
+        Ok(config) if config.otlp.enabled => Some(Otlp::start(config)),
+        _ => None,
+    };
+    #[cfg(feature = "otlp")]
+    let exporters = otlp.iter().flatten().map(|otlp| otlp.layer()).collect();
+    #[cfg(not(feature = "otlp"))]
+    let exporters = Vec::new();
+
+    let default_logging = LoggingConfig::default();
+    let log_filter = logging::init(config.as_ref().map_or(&default_logging, |config| &config.logging), exporters)?;
+    let log_filter = Arc::new(log_filter);
+
+    let config = config?;
+
+    #[cfg(feature = "otlp")]
+    let otlp = otlp.transpose()?;
+    #[cfg(feature = "otlp")]
+    if otlp.is_some() {
+        info!("Exporting metrics and spans to {}", config.otlp.endpoint);
+    }
+    #[cfg(not(feature = "otlp"))]
+    if config.otlp.enabled {
+        tracing::warn!("otlp.enabled is set, but this build doesn't support OTLP export. Build with --features otlp.");
+    }
+
+    AccessLog::init(&config.access_log)?;
+
+    let (config_tx, config_rx) = watch::channel(Arc::new(config));
+    Arc::clone(&log_filter).watch(config_rx.clone());
+
+    let readiness = Arc::new(Readiness::new());
+    let admin_config = config_rx.borrow().admin.clone();
+    if admin_config.enabled {
+        admin::serve(&admin_config, Arc::clone(&readiness), log_filter).await?;
+    }
+
+    let source = KubernetesSource::infer().await?;
+    let client = source.client();
+    // Set from spec.nodeName through the downward API
+    let node_name = std::env::var("NODE_NAME").ok();
+    let router = Arc::new(Router::new(config_rx.clone(), Arc::new(source), node_name));
+
+    // The resource names the service and listeners, so it's applied before anything is seeded
+    let config = Arc::clone(&config_rx.borrow());
+    let resource = if config.custom_resource.enabled {
+        let resource = Arc::new(ResourceWatcher::new(client.clone(), &config));
+        let seeded = match until_signal(&mut signals, resource.seed(&config)).await {
+            Some(seeded) => seeded,
+            None => return Ok(()),
+        };
+
+        config_tx.send_replace(Arc::new(seeded));
+        resource.start(&config);
+        Some(resource)
+    } else {
+        None
+    };
+
+    reload::spawn(config_tx, resource.as_ref().map(|resource| resource.subscribe()))?;
+
+    // Competing before seeding, so the leader reports a missing or unsupported service while seeding retries
+    let leader_election = Arc::new(LeaderElector::new(client.clone(), &config_rx.borrow()));
+    let events = Arc::clone(&router.events);
+    leader_election.on_started_leading(move || events.set_leading(true));
+    let events = Arc::clone(&router.events);
+    leader_election.on_stopped_leading(move || events.set_leading(false));
+    leader_election.start();
+
+    match until_signal(&mut signals, router.seed()).await {
+        Some(seeded) => seeded?,
+        None => {
+            shutdown::release_leadership(&leader_election).await;
+            return Ok(());
+        }
+    }
+
+    let proxy = Arc::new(Proxy::new(config_rx.clone(), Arc::clone(&router)));
+    Arc::clone(&proxy).listen().await;
+
+    Arc::clone(&router).start_watchers(true).await;
+
+    let service_status = Arc::new(ServiceStatus::new(Arc::clone(&router), client, Arc::clone(&leader_election)));
+    service_status.start();
+
+    if let Some(resource) = resource {
+        Arc::new(ResourceStatus::new(resource, Arc::clone(&proxy), Arc::clone(&leader_election))).start();
+    }
+
+    readiness.set_ready(true);
+
+    let signal = signals.recv().await;
+    info!("Got {}, shutting down", signal);
+
+    let shutdown_config = config_rx.borrow().shutdown.clone();
+    shutdown::shutdown(&shutdown_config, &mut signals, &readiness, &leader_election, &service_status, &proxy, &router).await;
+
+    #[cfg(feature = "otlp")]
+    if let Some(otlp) = otlp {
+        // Blocks until the last batch is sent or times out
+        tokio::task::spawn_blocking(move || otlp.shutdown()).await.ok();
+    }
+
+    Ok(())
+}
+
+// Seeding retries until the cluster answers, which could be forever, so a shutdown signal stops it. Returns
+// None if one arrived first. Nothing is serving yet, so there's nothing to shut down gracefully.
+async fn until_signal<F: Future>(signals: &mut ShutdownSignals, future: F) -> Option<F::Output> {
+    tokio::select! {
+        output = future => Some(output),
+        signal = signals.recv() => {
+            info!("Got {} while starting up, exiting", signal);
+            None
+        }
+    }
+}
+
+// Compares the data paths, and io_uring if built with it, over loopback: node_balancer bench [MiB] [connections]
+async fn bench(args: &[String]) -> node_balancer::Result<()> {
+    logging::init(&LoggingConfig::default(), Vec::new())?;
+
+    let parse = |i: usize, default: u64| match args.get(i) {
+        Some(arg) => arg.parse::<u64>().ok().filter(|v| *v > 0),
+        None => Some(default),
+    };
+
+    let (mib, connections) = match (parse(0, 4096), parse(1, 4)) {
+        (Some(mib), Some(connections)) => (mib, connections as usize),
+        _ => {
+            eprintln!("usage: node_balancer bench [MiB] [connections]");
+            std::process::exit(2);
+        }
+    };
+
+    println!("Sending {} MiB over {} connections through each data path", mib, connections);
 
-    let config = Arc::new(Config::from_envvar());
+    for data_path in &[DataPath::Copy, DataPath::Splice] {
+        let result = proxy::benchmark(data_path.clone(), mib * 1024 * 1024, connections).await
+            .map_err(NodeBalancerError::IOError)?;
+        println!("{}", result);
+    }
 
-    let router = Router::new(Arc::clone(&config)).await;
-    let router = Arc::new(router);
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    match proxy::benchmark_io_uring(mib * 1024 * 1024, connections, Config::default().io_uring.buffer_size).await {
+        Ok(result) => println!("{}", result),
+        Err(e) => println!("io_uring: unavailable, {}", e),
+    }
 
-    router.seed().await.expect("Seeding router failed");
-    router.start_watchers(false).await; // Blocks
+    Ok(())
 }
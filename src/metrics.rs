@@ -0,0 +1,81 @@
+use once_cell::sync::Lazy;
+use prometheus::{Encoder, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+pub struct Metrics {
+    pub registry: Registry,
+    pub active_connections: IntGaugeVec,
+    pub draining_backends: IntGaugeVec,
+    pub drain_remaining_connections: IntGaugeVec,
+    pub drain_closed_connections: IntCounterVec,
+    pub rejected_connections: IntCounterVec,
+    pub closed_connections: IntCounterVec,
+    pub queued_connections: IntGauge,
+    pub access_log_dropped: IntCounter,
+    pub leader: IntGauge,
+    pub cross_zone_connections: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Metrics {
+        let registry = Registry::new_custom(Some("node_balancer".to_owned()), None)
+            .expect("metrics registry");
+
+        let metrics = Metrics {
+            active_connections: IntGaugeVec::new(
+                Opts::new("active_connections", "Connections currently being proxied"),
+                &["listener"],
+            ).unwrap(),
+            draining_backends: IntGaugeVec::new(
+                Opts::new("draining_backends", "Nodes currently being drained"),
+                &["kind"],
+            ).unwrap(),
+            drain_remaining_connections: IntGaugeVec::new(
+                Opts::new("drain_remaining_connections", "Connections still open through a draining node"),
+                &["kind", "name"],
+            ).unwrap(),
+            drain_closed_connections: IntCounterVec::new(
+                Opts::new("drain_closed_connections_total", "Connections closed because their drain timed out"),
+                &["kind"],
+            ).unwrap(),
+            rejected_connections: IntCounterVec::new(
+                Opts::new("rejected_connections_total", "Connections closed without being proxied because a limit was reached or the client was not allowed"),
+                &["listener", "reason"],
+            ).unwrap(),
+            closed_connections: IntCounterVec::new(
+                Opts::new("closed_connections_total", "Proxied connections that ended, by why they ended"),
+                &["listener", "reason"],
+            ).unwrap(),
+            queued_connections: IntGauge::new("queued_connections", "Connections waiting for a limit to allow them").unwrap(),
+            access_log_dropped: IntCounter::new("access_log_dropped_total", "Access log records dropped because the writer couldn't keep up").unwrap(),
+            leader: IntGauge::new("leader", "1 while this replica holds the leader lease").unwrap(),
+            cross_zone_connections: IntCounter::new("cross_zone_connections_total", "Connections routed to a node outside the balancer's zone while topology is enabled").unwrap(),
+            registry,
+        };
+
+        metrics.registry.register(Box::new(metrics.active_connections.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.draining_backends.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.drain_remaining_connections.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.drain_closed_connections.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.rejected_connections.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.closed_connections.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.queued_connections.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.access_log_dropped.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.leader.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.cross_zone_connections.clone())).unwrap();
+
+        metrics
+    }
+
+    /// Renders every metric in the Prometheus text format
+    pub fn encode(&self) -> String {
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf).expect("encoding metrics");
+        String::from_utf8(buf).expect("metrics are utf-8")
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
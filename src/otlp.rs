@@ -0,0 +1,305 @@
+use crate::{Config, Result, NodeBalancerError};
+use crate::logging::ExportLayer;
+use crate::metrics::metrics;
+use opentelemetry::{KeyValue, InstrumentationScope};
+use opentelemetry::metrics::{Meter, MeterProvider};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use parking_lot::Mutex;
+use prometheus::proto::{MetricFamily, MetricType};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+// Mounted into every pod, naming the namespace the balancer itself runs in
+const NAMESPACE_PATH: &str = "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+
+// Longest a registry snapshot is reused for, bounding how stale a value can be when collections come closer
+// together than the export interval
+const MAX_SNAPSHOT_AGE: Duration = Duration::from_secs(1);
+
+/// Pushes the balancer's spans and metrics to an OpenTelemetry collector over OTLP/HTTP
+pub struct Otlp {
+    tracer_provider: SdkTracerProvider,
+    meter_provider: SdkMeterProvider,
+    meter: Meter,
+    // Metric families already bridged, so each gets one instrument
+    bridged: Mutex<HashSet<String>>,
+    snapshot: Arc<Snapshot>,
+}
+
+impl Otlp {
+    /// Starts exporting. Called before logging is set up so the exporter sees every span, so it doesn't log.
+    pub fn start(config: &Config) -> Result<Arc<Otlp>> {
+        let otlp = &config.otlp;
+        let resource = resource(config);
+        let endpoint = otlp.endpoint.trim_end_matches('/');
+
+        let span_exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint))
+            .with_timeout(otlp.timeout)
+            .build()
+            .map_err(|e| NodeBalancerError::OtlpError(e.to_string()))?;
+
+        let metric_exporter = MetricExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/metrics", endpoint))
+            .with_timeout(otlp.timeout)
+            .build()
+            .map_err(|e| NodeBalancerError::OtlpError(e.to_string()))?;
+
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_batch_exporter(span_exporter)
+            .with_resource(resource.clone())
+            .build();
+
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metric_exporter).with_interval(otlp.export_interval).build())
+            .with_resource(resource)
+            .build();
+
+        let meter = meter_provider.meter_with_scope(scope());
+
+        let otlp = Arc::new(Otlp {
+            tracer_provider,
+            meter_provider,
+            meter,
+            bridged: Mutex::new(HashSet::new()),
+            snapshot: Arc::new(Snapshot::new(MAX_SNAPSHOT_AGE.min(otlp.export_interval / 2))),
+        });
+
+        otlp.bridge_metrics();
+        Arc::clone(&otlp).spawn_bridge(config.otlp.export_interval);
+
+        Ok(otlp)
+    }
+
+    /// The layer sending spans to the collector, for `logging::init`
+    pub fn layer(&self) -> ExportLayer {
+        let tracer = self.tracer_provider.tracer_with_scope(scope());
+        Box::new(tracing_opentelemetry::layer().with_tracer(tracer))
+    }
+
+    // Keeps bridging metric families as they appear, Prometheus only reports labelled metrics once they have a
+    // value
+    fn spawn_bridge(self: Arc<Self>, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                self.bridge_metrics();
+            }
+        });
+    }
+
+    /// Sends anything still buffered, called on the way out
+    pub fn shutdown(&self) {
+        if let Err(e) = self.tracer_provider.shutdown() {
+            warn!("Failed to flush spans: {}", e);
+        }
+
+        if let Err(e) = self.meter_provider.shutdown() {
+            warn!("Failed to flush metrics: {}", e);
+        }
+    }
+
+    // Registers an observable instrument for each Prometheus metric family that doesn't have one yet, reading
+    // the registry whenever the reader collects
+    fn bridge_metrics(&self) {
+        let mut bridged = self.bridged.lock();
+
+        for family in metrics().registry.gather() {
+            if !bridged.insert(family.get_name().to_owned()) {
+                continue;
+            }
+
+            let prom_name = family.get_name().to_owned();
+            let name = instrument_name(&prom_name);
+            let help = family.get_help().to_owned();
+            let snapshot = Arc::clone(&self.snapshot);
+
+            match family.get_field_type() {
+                MetricType::COUNTER => {
+                    self.meter.f64_observable_counter(name)
+                        .with_description(help)
+                        .with_callback(move |observer| observe(&snapshot.families(), &prom_name, |value, attributes| observer.observe(value, attributes)))
+                        .build();
+                }
+                MetricType::GAUGE => {
+                    self.meter.f64_observable_gauge(name)
+                        .with_description(help)
+                        .with_callback(move |observer| observe(&snapshot.families(), &prom_name, |value, attributes| observer.observe(value, attributes)))
+                        .build();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// The registry as read for the collection in progress. The reader runs every bridged instrument's callback
+// back to back, so they share one gather rather than each reading the whole registry.
+struct Snapshot {
+    max_age: Duration,
+    taken: Mutex<Option<(Instant, Arc<Vec<MetricFamily>>)>>,
+}
+
+impl Snapshot {
+    fn new(max_age: Duration) -> Snapshot {
+        Snapshot {
+            max_age,
+            taken: Mutex::new(None),
+        }
+    }
+
+    fn families(&self) -> Arc<Vec<MetricFamily>> {
+        let mut taken = self.taken.lock();
+
+        match &*taken {
+            Some((at, families)) if at.elapsed() < self.max_age => Arc::clone(families),
+            _ => {
+                let families = Arc::new(metrics().registry.gather());
+                *taken = Some((Instant::now(), Arc::clone(&families)));
+                families
+            }
+        }
+    }
+}
+
+fn scope() -> InstrumentationScope {
+    InstrumentationScope::builder(env!("CARGO_PKG_NAME"))
+        .with_version(env!("CARGO_PKG_VERSION"))
+        .build()
+}
+
+// Identifies this instance and the service it balances. HOSTNAME is the pod name when running in a cluster.
+fn resource(config: &Config) -> Resource {
+    let mut attributes = vec![
+        KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        KeyValue::new("node_balancer.service.namespace", config.service.namespace.clone()),
+        KeyValue::new("node_balancer.service.name", config.service.name.clone()),
+    ];
+
+    // The balancer's own namespace, which needn't be the service's
+    if let Ok(namespace) = std::fs::read_to_string(NAMESPACE_PATH) {
+        attributes.push(KeyValue::new("k8s.namespace.name", namespace.trim().to_owned()));
+    }
+
+    if let Ok(hostname) = std::env::var("HOSTNAME") {
+        attributes.push(KeyValue::new("service.instance.id", hostname.clone()));
+        attributes.push(KeyValue::new("k8s.pod.name", hostname));
+    }
+
+    attributes.extend(config.otlp.resource_attributes.iter().map(|(key, value)| KeyValue::new(key.clone(), value.clone())));
+
+    Resource::builder_empty()
+        .with_service_name(env!("CARGO_PKG_NAME"))
+        .with_attributes(attributes)
+        .build()
+}
+
+// node_balancer_closed_connections_total -> node_balancer.closed_connections
+fn instrument_name(prom_name: &str) -> String {
+    let name = prom_name.strip_prefix("node_balancer_").unwrap_or(prom_name);
+    format!("node_balancer.{}", name.strip_suffix("_total").unwrap_or(name))
+}
+
+fn observe<F: Fn(f64, &[KeyValue])>(families: &[MetricFamily], prom_name: &str, observe: F) {
+    let family = families.iter().find(|family| family.get_name() == prom_name);
+
+    for metric in family.iter().flat_map(|family| family.get_metric()) {
+        let attributes: Vec<KeyValue> = metric.get_label().iter()
+            .map(|label| KeyValue::new(label.get_name().to_owned(), label.get_value().to_owned()))
+            .collect();
+
+        let value = match family.map(MetricFamily::get_field_type) {
+            Some(MetricType::COUNTER) => metric.get_counter().get_value(),
+            _ => metric.get_gauge().get_value(),
+        };
+        observe(value, &attributes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{Body, Request, Response, Server};
+    use hyper::service::{make_service_fn, service_fn};
+    use std::convert::Infallible;
+    use tokio::sync::mpsc;
+
+    // Accepts any OTLP/HTTP request, passing on the path and body
+    async fn collector() -> (String, mpsc::UnboundedReceiver<(String, Vec<u8>)>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let make_service = make_service_fn(move |_| {
+            let tx = tx.clone();
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let tx = tx.clone();
+
+                    async move {
+                        let path = req.uri().path().to_owned();
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap_or_default();
+                        let _ = tx.send((path, body.to_vec()));
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let endpoint = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        (endpoint, rx)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn exports_bridged_metrics_to_collector() {
+        let (endpoint, mut requests) = collector().await;
+        metrics().cross_zone_connections.inc();
+
+        let mut config = Config::default();
+        config.otlp.endpoint = endpoint;
+        config.otlp.export_interval = Duration::from_millis(100);
+        let otlp = Otlp::start(&config).unwrap();
+
+        let body = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                match requests.recv().await {
+                    Some((path, body)) if path == "/v1/metrics" => break body,
+                    Some(_) => continue,
+                    None => panic!("collector stopped"),
+                }
+            }
+        }).await.expect("no metrics exported");
+
+        let name = b"node_balancer.cross_zone_connections";
+        assert!(body.windows(name.len()).any(|window| window == name));
+
+        tokio::task::spawn_blocking(move || otlp.shutdown()).await.unwrap();
+    }
+
+    #[test]
+    fn snapshot_is_shared_until_max_age() {
+        let snapshot = Snapshot::new(Duration::from_secs(60));
+        assert!(Arc::ptr_eq(&snapshot.families(), &snapshot.families()));
+
+        let snapshot = Snapshot::new(Duration::ZERO);
+        assert!(!Arc::ptr_eq(&snapshot.families(), &snapshot.families()));
+    }
+
+    #[test]
+    fn instrument_names() {
+        assert_eq!(instrument_name("node_balancer_closed_connections_total"), "node_balancer.closed_connections");
+        assert_eq!(instrument_name("node_balancer_leader"), "node_balancer.leader");
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{Config, NodeBalancerError};
+use crate::access_log::{access_log, AccessLogRecord};
+use crate::config::ListenerConfig;
+use crate::connections::{ConnectionGuard, ConnectionPermit};
+use crate::metrics::metrics;
+use crate::proxy::{CloseReason, Proxy};
+use crate::proxy::copy::{CopyOptions, CopyOutcome};
+use crate::router::Router;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+use tracing::{debug, info_span, warn, Instrument, Span};
+
+/// A connection that made it past the source ranges and limits, waiting to be routed
+pub struct AcceptedConnection {
+    pub client_addr: SocketAddr,
+    pub port: u16,
+    pub accepted: Instant,
+    // As of when the connection was accepted, so reloads don't change it halfway
+    pub config: Arc<Config>,
+    pub listener_config: ListenerConfig,
+    // Only held, so the connection counts against the limits until it is dropped
+    #[allow(dead_code)]
+    pub permit: ConnectionPermit,
+    // Filled in as the connection is routed, for the access log
+    pub pod: Option<String>,
+    pub node: Option<String>,
+    pub node_addr: Option<SocketAddr>,
+    pub connect_latency: Option<Duration>,
+    pub bytes_from_client: u64,
+    pub bytes_from_backend: u64,
+    // The connection's span, which follows it onto io_uring workers
+    pub span: Span,
+}
+
+impl AcceptedConnection {
+    pub fn new(client_addr: SocketAddr, port: u16, accepted: Instant, config: Arc<Config>, listener_config: ListenerConfig, permit: ConnectionPermit) -> Self {
+        AcceptedConnection {
+            client_addr,
+            port,
+            accepted,
+            config,
+            listener_config,
+            permit,
+            pod: None,
+            node: None,
+            node_addr: None,
+            connect_latency: None,
+            bytes_from_client: 0,
+            bytes_from_backend: 0,
+            span: Span::current(),
+        }
+    }
+
+    /// Picks a destination and connects to it with `connect`, returning the close reason on failure
+    pub async fn open<S, F, Fut>(&mut self, router: &Router, connect: F) -> Result<(S, ConnectionGuard), (CloseReason, NodeBalancerError)>
+    where
+        F: Fn(SocketAddr) -> Fut,
+        Fut: Future<Output = io::Result<S>>,
+    {
+        let strategy = self.listener_config.strategy(&self.config);
+        let address_family = self.listener_config.address_family(&self.config);
+        let timeouts = self.listener_config.timeouts(&self.config);
+
+        let destination = router.get_destination(&self.listener_config.service_port(), &strategy)
+            .map_err(|e| (CloseReason::NoDestination, e))?;
+
+        self.pod = destination.pod.clone();
+        self.node = Some(destination.node.clone());
+        if let Some(pod) = &destination.pod {
+            self.span.record("pod", pod.as_str());
+        }
+        self.span.record("node", destination.node.as_str());
+
+        let guard = router.connections.register(&destination.node, self.port);
+        let connecting = Instant::now();
+
+        let connected = Proxy::connect(router, &destination, self.client_addr.ip(), &address_family, &timeouts, connect)
+            .instrument(info_span!(parent: &self.span, "connect"))
+            .await;
+
+        match connected {
+            Ok((outbound, node_addr)) => {
+                self.node_addr = Some(node_addr);
+                self.span.record("destination", tracing::field::display(node_addr));
+                self.connect_latency = Some(connecting.elapsed());
+                Ok((outbound, guard))
+            }
+            Err(e @ NodeBalancerError::ConnectTimeout(_)) => Err((CloseReason::ConnectTimeout, e)),
+            Err(e) => Err((CloseReason::ConnectFailed, e)),
+        }
+    }
+
+    pub fn copy_options(&self) -> CopyOptions {
+        let timeouts = self.listener_config.timeouts(&self.config);
+
+        CopyOptions {
+            data_path: self.listener_config.data_path(&self.config),
+            idle: timeouts.idle,
+            half_close: timeouts.half_close,
+            deadline: timeouts.max_lifetime.map(|max_lifetime| self.accepted + max_lifetime),
+        }
+    }
+
+    /// Records what the copy moved, returning how it ended
+    pub fn copied(&mut self, outcome: CopyOutcome) -> (CloseReason, Option<NodeBalancerError>) {
+        self.bytes_from_client = outcome.bytes_from_client;
+        self.bytes_from_backend = outcome.bytes_from_backend;
+        (outcome.reason, outcome.error.map(NodeBalancerError::IOError))
+    }
+
+    pub fn closed(&self, reason: CloseReason, error: Option<NodeBalancerError>) {
+        let duration = self.accepted.elapsed();
+        self.span.record("reason", reason.as_str());
+
+        match &error {
+            Some(e) => warn!("Connection from {} on port {} closed after {:?} ({}): {}", self.client_addr, self.port, duration, reason, e),
+            None => debug!("Connection from {} on port {} closed after {:?} ({})", self.client_addr, self.port, duration, reason),
+        }
+
+        metrics().closed_connections.with_label_values(&[&self.port.to_string(), reason.as_str()]).inc();
+
+        if let Some(log) = access_log().filter(|log| log.sampled(error.is_some())) {
+            log.write(&AccessLogRecord {
+                time: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+                client_addr: self.client_addr,
+                listen_port: self.port,
+                pod: self.pod.as_deref(),
+                node: self.node.as_deref(),
+                node_addr: self.node_addr,
+                connect_ms: self.connect_latency.map(|latency| latency.as_secs_f64() * 1000.0),
+                bytes_from_client: self.bytes_from_client,
+                bytes_to_client: self.bytes_from_backend,
+                duration_ms: duration.as_secs_f64() * 1000.0,
+                reason: reason.as_str(),
+                error: error.map(|e| e.to_string()),
+            });
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use crate::config::DataPath;
+use crate::proxy::copy::{self, CopyOptions};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+const WRITE_SIZE: usize = 64 * 1024;
+
+pub struct BenchmarkResult {
+    pub name: String,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    // User and system time of the whole process, so includes the sending and receiving ends too
+    pub cpu: Duration,
+}
+
+impl fmt::Display for BenchmarkResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mib = self.bytes as f64 / (1024.0 * 1024.0);
+
+        write!(f, "{:>8}: {:.0} MiB in {:.2?}, {:.1} MiB/s, {:.2?} CPU ({:.3} CPU seconds per GiB)",
+            self.name,
+            mib,
+            self.elapsed,
+            mib / self.elapsed.as_secs_f64(),
+            self.cpu,
+            self.cpu.as_secs_f64() / (mib / 1024.0),
+        )
+    }
+}
+
+fn options(data_path: DataPath) -> CopyOptions {
+    CopyOptions {
+        data_path,
+        idle: None,
+        half_close: None,
+        deadline: None,
+    }
+}
+
+/// Sends `bytes` split over `connections` through the data path over loopback, from a local sender to a
+/// local receiver, timing it and measuring the CPU used along the way
+pub async fn benchmark(data_path: DataPath, bytes: u64, connections: usize) -> io::Result<BenchmarkResult> {
+    let proxy = TcpListener::bind("127.0.0.1:0").await?;
+    let proxy_addr = proxy.local_addr()?;
+    let receiver = TcpListener::bind("127.0.0.1:0").await?;
+    let receiver_addr = receiver.local_addr()?;
+    let name = data_path.to_string();
+
+    let proxying = tokio::spawn(async move {
+        let mut copies = Vec::with_capacity(connections);
+        for _ in 0..connections {
+            let (mut inbound, _) = proxy.accept().await?;
+            let mut outbound = TcpStream::connect(receiver_addr).await?;
+            let options = options(data_path.clone());
+
+            copies.push(tokio::spawn(async move {
+                copy::copy_bidirectional(&mut inbound, &mut outbound, &options, futures::future::pending()).await
+            }));
+        }
+
+        for copy in copies {
+            if let Some(e) = copy.await.map_err(io::Error::other)?.error {
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    });
+
+    drive(name, proxy_addr, receiver, proxying, bytes, connections).await
+}
+
+/// Like `benchmark`, with the proxying side accepting, connecting and copying on an io_uring runtime
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub async fn benchmark_io_uring(bytes: u64, connections: usize, buffer_size: usize) -> io::Result<BenchmarkResult> {
+    use crate::proxy::uring::{self, BufferPool};
+    use std::rc::Rc;
+
+    let receiver = TcpListener::bind("127.0.0.1:0").await?;
+    let receiver_addr = receiver.local_addr()?;
+    let (bound_tx, bound_rx) = tokio::sync::oneshot::channel();
+
+    let thread = std::thread::spawn(move || -> io::Result<()> {
+        let runtime = tokio_uring::Runtime::new(&tokio_uring::builder())?;
+
+        runtime.block_on(async move {
+            let proxy = tokio_uring::net::TcpListener::bind("127.0.0.1:0".parse().unwrap())?;
+            let _ = bound_tx.send(proxy.local_addr()?);
+            let buffers = BufferPool::new(buffer_size);
+
+            let mut copies = Vec::with_capacity(connections);
+            for _ in 0..connections {
+                let (inbound, _) = proxy.accept().await?;
+                let outbound = tokio_uring::net::TcpStream::connect(receiver_addr).await?;
+                let buffers = Rc::clone(&buffers);
+
+                copies.push(tokio_uring::spawn(async move {
+                    uring::copy_bidirectional(&inbound, &outbound, &buffers, &options(DataPath::Copy), futures::future::pending()).await
+                }));
+            }
+
+            for copy in copies {
+                if let Some(e) = copy.await.map_err(io::Error::other)?.error {
+                    return Err(e);
+                }
+            }
+
+            Ok(())
+        })
+    });
+
+    let proxy_addr = match bound_rx.await {
+        Ok(addr) => addr,
+        // The runtime or listener failed, the thread has the error
+        Err(_) => return Err(thread.join().map_err(|_| io::Error::other("io_uring thread panicked"))?.unwrap_err()),
+    };
+
+    let proxying = tokio::task::spawn_blocking(move || thread.join().map_err(|_| io::Error::other("io_uring thread panicked"))?);
+    drive("io_uring".to_owned(), proxy_addr, receiver, proxying, bytes, connections).await
+}
+
+// Runs the senders and receivers on either side of the proxying task
+async fn drive(name: String, proxy_addr: SocketAddr, receiver: TcpListener, proxying: JoinHandle<io::Result<()>>, bytes: u64, connections: usize) -> io::Result<BenchmarkResult> {
+    let per_connection = bytes / connections as u64;
+    let cpu_before = cpu_time();
+    let started = Instant::now();
+
+    let mut senders = Vec::with_capacity(connections);
+    let mut receivers = Vec::with_capacity(connections);
+    for _ in 0..connections {
+        senders.push(tokio::spawn(send(TcpStream::connect(proxy_addr).await?, per_connection)));
+
+        let (received, _) = receiver.accept().await?;
+        receivers.push(tokio::spawn(receive(received)));
+    }
+
+    let mut received = 0;
+    for receiver in receivers {
+        received += receiver.await.map_err(io::Error::other)??;
+    }
+
+    for sender in senders {
+        sender.await.map_err(io::Error::other)??;
+    }
+
+    proxying.await.map_err(io::Error::other)??;
+
+    Ok(BenchmarkResult {
+        name,
+        bytes: received,
+        elapsed: started.elapsed(),
+        cpu: cpu_time().saturating_sub(cpu_before),
+    })
+}
+
+async fn send(mut stream: TcpStream, bytes: u64) -> io::Result<()> {
+    let buf = vec![0xa5u8; WRITE_SIZE];
+    let mut remaining = bytes;
+
+    while remaining > 0 {
+        let n = remaining.min(WRITE_SIZE as u64) as usize;
+        stream.write_all(&buf[..n]).await?;
+        remaining -= n as u64;
+    }
+
+    stream.shutdown().await?;
+
+    // Wait for the receiver's side to close too
+    while stream.read(&mut [0u8; 1]).await? > 0 {}
+    Ok(())
+}
+
+async fn receive(mut stream: TcpStream) -> io::Result<u64> {
+    let mut buf = vec![0u8; WRITE_SIZE];
+    let mut received = 0;
+
+    loop {
+        match stream.read(&mut buf).await? {
+            0 => break,
+            n => received += n as u64,
+        }
+    }
+
+    stream.shutdown().await?;
+    Ok(received)
+}
+
+fn cpu_time() -> Duration {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) };
+
+    let to_duration = |tv: libc::timeval| Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+    to_duration(usage.ru_utime) + to_duration(usage.ru_stime)
+}
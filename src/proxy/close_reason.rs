@@ -0,0 +1,42 @@
+use std::fmt;
+
+/// Why a proxied connection ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloseReason {
+    // Both sides finished sending
+    Completed,
+    NoDestination,
+    ConnectFailed,
+    ConnectTimeout,
+    ClientError,
+    BackendError,
+    Idle,
+    HalfCloseTimeout,
+    MaxLifetime,
+    // Closed by the connection tracker, because its backend drained or the balancer is shutting down
+    ForceClosed,
+}
+
+impl CloseReason {
+    /// Label for the closed connections metric
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CloseReason::Completed => "completed",
+            CloseReason::NoDestination => "no_destination",
+            CloseReason::ConnectFailed => "connect_failed",
+            CloseReason::ConnectTimeout => "connect_timeout",
+            CloseReason::ClientError => "client_error",
+            CloseReason::BackendError => "backend_error",
+            CloseReason::Idle => "idle",
+            CloseReason::HalfCloseTimeout => "half_close_timeout",
+            CloseReason::MaxLifetime => "max_lifetime",
+            CloseReason::ForceClosed => "force_closed",
+        }
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -0,0 +1,116 @@
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Connects to the first address that answers. Attempts start in order, each one `delay` after the previous
+/// unless the previous fails sooner, so a dead address family doesn't stall the connection (RFC 8305).
+/// `connect` makes a single attempt, so the same logic serves every kind of socket. Returns the address that
+/// answered alongside the socket.
+pub async fn happy_eyeballs<S, F, Fut>(addrs: &[SocketAddr], delay: Duration, connect: F) -> io::Result<(S, SocketAddr)>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = io::Result<S>>,
+{
+    let attempt = |addr: SocketAddr| {
+        let connecting = connect(addr);
+        async move { connecting.await.map(|stream| (stream, addr)) }
+    };
+
+    let mut remaining = addrs.iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = io::Error::new(io::ErrorKind::AddrNotAvailable, "no addresses to connect to");
+
+    if let Some(addr) = remaining.next() {
+        attempts.push(attempt(*addr));
+    }
+
+    while !attempts.is_empty() {
+        let next_attempt = tokio::time::sleep(delay);
+        tokio::pin!(next_attempt);
+
+        tokio::select! {
+            res = attempts.next() => match res {
+                Some(Ok(stream)) => return Ok(stream),
+                Some(Err(e)) => {
+                    last_error = e;
+
+                    if let Some(addr) = remaining.next() {
+                        attempts.push(attempt(*addr));
+                    }
+                }
+                None => {}
+            },
+
+            _ = &mut next_attempt => {
+                if let Some(addr) = remaining.next() {
+                    attempts.push(attempt(*addr));
+                }
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use tokio::time::Instant;
+
+    fn addrs() -> Vec<SocketAddr> {
+        vec!["[fd00::1]:80".parse().unwrap(), "10.0.0.1:80".parse().unwrap(), "10.0.0.2:80".parse().unwrap()]
+    }
+
+    fn refused() -> io::Error {
+        io::Error::from(io::ErrorKind::ConnectionRefused)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn falls_back_straight_away_when_an_attempt_fails() {
+        let start = Instant::now();
+        let first = addrs()[0];
+
+        let (_, addr) = happy_eyeballs(&addrs(), Duration::from_secs(1), |addr| async move {
+            if addr == first { Err(refused()) } else { Ok(()) }
+        }).await.unwrap();
+
+        assert_eq!(addr, addrs()[1]);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn starts_the_next_attempt_after_the_delay() {
+        let start = Instant::now();
+        let first = addrs()[0];
+        let started = Mutex::new(Vec::new());
+
+        let (_, addr) = happy_eyeballs(&addrs(), Duration::from_millis(250), |addr| {
+            started.lock().push((addr, start.elapsed()));
+            async move {
+                if addr == first {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                } else {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+
+                Ok(())
+            }
+        }).await.unwrap();
+
+        assert_eq!(addr, addrs()[1]);
+        assert_eq!(start.elapsed(), Duration::from_millis(350));
+        assert_eq!(*started.lock(), vec![(addrs()[0], Duration::ZERO), (addrs()[1], Duration::from_millis(250))]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn returns_the_last_error_when_everything_fails() {
+        let err = happy_eyeballs::<(), _, _>(&addrs(), Duration::from_millis(250), |_| async { Err(refused()) }).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+
+        let err = happy_eyeballs::<(), _, _>(&[], Duration::from_millis(250), |_| async { Ok(()) }).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrNotAvailable);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::config::DataPath;
+use crate::proxy::CloseReason;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::net::tcp::{ReadHalf, WriteHalf};
+use tokio::time::{sleep_until, Instant};
+use tracing::debug;
+
+const BUFFER_SIZE: usize = 16 * 1024;
+
+// How copying one direction ended, with which side failed if it didn't finish cleanly
+pub type DirectionResult = Result<(), (CloseReason, io::Error)>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    FromClient,
+    FromBackend,
+}
+
+impl Direction {
+    pub fn read_error(self) -> CloseReason {
+        match self {
+            Direction::FromClient => CloseReason::ClientError,
+            Direction::FromBackend => CloseReason::BackendError,
+        }
+    }
+
+    pub fn write_error(self) -> CloseReason {
+        match self {
+            Direction::FromClient => CloseReason::BackendError,
+            Direction::FromBackend => CloseReason::ClientError,
+        }
+    }
+}
+
+/// How a connection ended and how much it carried
+pub struct CopyOutcome {
+    pub reason: CloseReason,
+    pub error: Option<io::Error>,
+    pub bytes_from_client: u64,
+    pub bytes_from_backend: u64,
+}
+
+pub struct CopyOptions {
+    pub data_path: DataPath,
+    pub idle: Option<Duration>,
+    pub half_close: Option<Duration>,
+    // Counted from when the connection was accepted
+    pub deadline: Option<Instant>,
+}
+
+/// Copies both ways until both sides finish, a timeout is hit or `closed` resolves. Dropping the streams
+/// afterwards closes them.
+pub async fn copy_bidirectional<F: Future<Output = ()>>(inbound: &mut TcpStream, outbound: &mut TcpStream, options: &CopyOptions, closed: F) -> CopyOutcome {
+    let (mut ri, mut wi) = inbound.split();
+    let (mut ro, mut wo) = outbound.split();
+
+    let activity = Activity::new();
+
+    let splice = options.data_path == DataPath::Splice;
+    let client_to_server = copy_direction(&mut ri, &mut wo, splice, &activity, Direction::FromClient);
+    let server_to_client = copy_direction(&mut ro, &mut wi, splice, &activity, Direction::FromBackend);
+
+    supervise(client_to_server, server_to_client, &activity, options, closed).await
+}
+
+/// Drives both directions of a connection, applying the timeouts in `options`. The directions update
+/// `activity` whenever bytes move.
+pub async fn supervise<A, B, F>(client_to_server: A, server_to_client: B, activity: &Activity, options: &CopyOptions, closed: F) -> CopyOutcome
+where
+    A: Future<Output = DirectionResult>,
+    B: Future<Output = DirectionResult>,
+    F: Future<Output = ()>,
+{
+    let (reason, error) = run_until_closed(client_to_server, server_to_client, activity, options, closed).await;
+
+    CopyOutcome {
+        reason,
+        error,
+        bytes_from_client: activity.from_client.load(Ordering::Relaxed),
+        bytes_from_backend: activity.from_backend.load(Ordering::Relaxed),
+    }
+}
+
+async fn run_until_closed<A, B, F>(client_to_server: A, server_to_client: B, activity: &Activity, options: &CopyOptions, closed: F) -> (CloseReason, Option<io::Error>)
+where
+    A: Future<Output = DirectionResult>,
+    B: Future<Output = DirectionResult>,
+    F: Future<Output = ()>,
+{
+    tokio::pin!(client_to_server, server_to_client, closed);
+
+    let mut client_done = false;
+    let mut server_done = false;
+    // When the first direction finished
+    let mut half_closed: Option<Instant> = None;
+
+    // Sleeps that are not configured get a deadline that never comes, and are disabled in the select
+    let far_future = Instant::now() + Duration::from_secs(86400 * 365);
+
+    loop {
+        let idle_deadline = options.idle.map(|idle| activity.last() + idle);
+        // Once one side has finished, the other gets half_close without bytes moving before it's closed
+        let linger = half_closed.zip(options.half_close).map(|(at, half_close)| at.max(activity.last()) + half_close);
+
+        let res = tokio::select! {
+            res = &mut client_to_server, if !client_done => {
+                client_done = true;
+                res
+            }
+
+            res = &mut server_to_client, if !server_done => {
+                server_done = true;
+                res
+            }
+
+            _ = sleep_until(idle_deadline.unwrap_or(far_future)), if idle_deadline.is_some() => {
+                // Bytes may have moved since the deadline was worked out
+                if options.idle.is_some_and(|idle| activity.last().elapsed() >= idle) {
+                    return (CloseReason::Idle, None);
+                }
+
+                continue;
+            }
+
+            _ = sleep_until(linger.unwrap_or(far_future)), if linger.is_some() => {
+                // Bytes may have moved since the deadline was worked out
+                let lingered = half_closed.zip(options.half_close)
+                    .is_some_and(|(at, half_close)| at.max(activity.last()).elapsed() >= half_close);
+                if lingered {
+                    return (CloseReason::HalfCloseTimeout, None);
+                }
+
+                continue;
+            }
+
+            _ = sleep_until(options.deadline.unwrap_or(far_future)), if options.deadline.is_some() => {
+                return (CloseReason::MaxLifetime, None);
+            }
+
+            _ = &mut closed => return (CloseReason::ForceClosed, None),
+        };
+
+        if let Err((reason, e)) = res {
+            return (reason, Some(e));
+        }
+
+        if client_done && server_done {
+            return (CloseReason::Completed, None);
+        }
+
+        if half_closed.is_none() {
+            half_closed = Some(Instant::now());
+        }
+    }
+}
+
+// The last time bytes moved in either direction, kept as an offset from when copying started, and how many
+// moved each way
+pub struct Activity {
+    started: Instant,
+    last_ms: AtomicU64,
+    from_client: AtomicU64,
+    from_backend: AtomicU64,
+}
+
+impl Activity {
+    pub fn new() -> Activity {
+        Activity {
+            started: Instant::now(),
+            last_ms: AtomicU64::new(0),
+            from_client: AtomicU64::new(0),
+            from_backend: AtomicU64::new(0),
+        }
+    }
+
+    pub fn touch(&self) {
+        self.last_ms.store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Records bytes that were written out
+    pub fn moved(&self, direction: Direction, bytes: usize) {
+        let counter = match direction {
+            Direction::FromClient => &self.from_client,
+            Direction::FromBackend => &self.from_backend,
+        };
+
+        counter.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.touch();
+    }
+
+    fn last(&self) -> Instant {
+        self.started + Duration::from_millis(self.last_ms.load(Ordering::Relaxed))
+    }
+}
+
+async fn copy_direction(reader: &mut ReadHalf<'_>, writer: &mut WriteHalf<'_>, splice: bool, activity: &Activity, direction: Direction) -> DirectionResult {
+    #[cfg(target_os = "linux")]
+    if splice {
+        match crate::proxy::splice::Pipe::new() {
+            Ok(pipe) => {
+                if crate::proxy::splice::splice_one(reader, writer, &pipe, activity, direction).await? {
+                    return Ok(());
+                }
+
+                debug!("Sockets can't be spliced, copying instead");
+            }
+            Err(e) => debug!("Failed to create a pipe to splice through, copying instead: {}", e),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    if splice {
+        debug!("splice is only available on Linux, copying instead");
+    }
+
+    copy_one(reader, writer, activity, direction).await
+}
+
+// Copies until the reader reaches EOF, then shuts down the writer to pass the half-close on
+async fn copy_one<R, W>(reader: &mut R, writer: &mut W, activity: &Activity, direction: Direction) -> DirectionResult
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; BUFFER_SIZE];
+
+    loop {
+        let n = reader.read(&mut buf).await.map_err(|e| (direction.read_error(), e))?;
+        if n == 0 {
+            return writer.shutdown().await.map_err(|e| (direction.write_error(), e));
+        }
+
+        activity.touch();
+        writer.write_all(&buf[..n]).await.map_err(|e| (direction.write_error(), e))?;
+        activity.moved(direction, n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(half_close: Duration) -> CopyOptions {
+        CopyOptions {
+            data_path: DataPath::Copy,
+            idle: None,
+            half_close: Some(half_close),
+            deadline: None,
+        }
+    }
+
+    // A direction that moves a few bytes every `every`, `times` times, then finishes
+    async fn streaming(activity: &Activity, every: Duration, times: u32) -> DirectionResult {
+        for _ in 0..times {
+            tokio::time::sleep(every).await;
+            activity.moved(Direction::FromBackend, 10);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn half_close_allows_a_busy_direction_to_carry_on() {
+        let activity = Activity::new();
+        let options = options(Duration::from_secs(60));
+
+        let outcome = supervise(async { Ok(()) }, streaming(&activity, Duration::from_secs(30), 10), &activity, &options, std::future::pending()).await;
+
+        assert_eq!(outcome.reason, CloseReason::Completed);
+        assert_eq!(outcome.bytes_from_backend, 100);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn half_close_closes_a_quiet_direction() {
+        let activity = Activity::new();
+        let options = options(Duration::from_secs(60));
+        let started = Instant::now();
+
+        let outcome = supervise(async { Ok(()) }, streaming(&activity, Duration::from_secs(90), 10), &activity, &options, std::future::pending()).await;
+
+        assert_eq!(outcome.reason, CloseReason::HalfCloseTimeout);
+        assert_eq!(started.elapsed(), Duration::from_secs(60));
+    }
+}
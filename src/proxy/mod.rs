@@ -1 +1,22 @@
-mod proxy;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+mod proxy;
+pub use proxy::Proxy;
+
+mod accepted_connection;
+use accepted_connection::AcceptedConnection;
+
+mod close_reason;
+pub use close_reason::CloseReason;
+
+mod benchmark;
+pub use benchmark::{benchmark, BenchmarkResult};
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub use benchmark::benchmark_io_uring;
+
+mod bind;
+mod connect;
+mod copy;
+#[cfg(target_os = "linux")]
+mod splice;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
@@ -1,66 +1,388 @@
 use crate::{Config, Result, NodeBalancerError};
+use crate::config::{AddressFamily, ListenerConfig, OverLimit, PortRef, Timeouts};
+use crate::proxy::{bind, connect, AcceptedConnection, CloseReason};
+use crate::proxy::copy;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use crate::proxy::uring::UringWorkers;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use crate::config::IoUringConfig;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use rand::seq::SliceRandom;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use parking_lot::Mutex;
 use tokio::net::{TcpListener, TcpStream};
-use tokio::io;
-use tokio::io::AsyncWriteExt;
-use crate::router::Router;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use crate::router::{Router, Destination, Protocol};
+use crate::connections::ConnectionLimiter;
+use crate::metrics::metrics;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use tracing::field;
+
+// Wait after a failed accept, doubling while accepting keeps failing
+const MIN_ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(5);
+const MAX_ACCEPT_ERROR_DELAY: Duration = Duration::from_secs(1);
+
+// Accept errors are logged at most this often per listener
+const ACCEPT_ERROR_LOG_INTERVAL: Duration = Duration::from_secs(10);
 
 pub struct Proxy {
-    pub config: Arc<Config>,
+    pub config: watch::Receiver<Arc<Config>>,
     pub router: Arc<Router>,
+    // listen port -> accept loop
+    listeners: Mutex<HashMap<u16, RunningListener>>,
+    limiter: Arc<ConnectionLimiter>,
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    uring: Option<UringWorkers>,
+    reconciler: Mutex<Option<JoinHandle<()>>>,
+    stopped: AtomicBool,
+    // Identifies a connection across its log lines
+    next_connection_id: AtomicU64,
+}
+
+struct RunningListener {
+    addr: SocketAddr,
+    dual_stack: bool,
+    config: ListenerConfig,
+    handle: JoinHandle<()>,
 }
 
 impl Proxy {
-    pub fn new(config: Arc<Config>, router: Arc<Router>) -> Proxy {
+    pub fn new(config: watch::Receiver<Arc<Config>>, router: Arc<Router>) -> Proxy {
+        let io_uring = config.borrow().io_uring.clone();
+
+        #[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+        if io_uring.enabled {
+            warn!("io_uring.enabled is set, but this build doesn't support io_uring. Proxying on epoll instead.");
+        }
+
         Proxy {
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            uring: Self::start_uring(&io_uring, &router),
             config,
             router,
+            listeners: Mutex::new(HashMap::new()),
+            limiter: Arc::new(ConnectionLimiter::new()),
+            reconciler: Mutex::new(None),
+            stopped: AtomicBool::new(false),
+            next_connection_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.borrow())
+    }
+
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    fn start_uring(config: &IoUringConfig, router: &Arc<Router>) -> Option<UringWorkers> {
+        if !config.enabled {
+            return None;
+        }
+
+        match UringWorkers::start(config, Arc::clone(router)) {
+            Ok(workers) => {
+                info!("Proxying connections on {} io_uring workers", config.workers);
+                Some(workers)
+            }
+            Err(e) => {
+                warn!("io_uring is unavailable, proxying on epoll instead: {}", e);
+                None
+            }
         }
     }
 
     pub async fn listen(self: Arc<Self>) {
-        for port in self.config.ports {
-            let proxy = Arc::clone(&self);
-            tokio::spawn(async move {
-                proxy.start_listener(port).await;
-            });
+        self.reconcile().await;
+
+        let proxy = Arc::clone(&self);
+        *proxy.reconciler.lock() = Some(tokio::spawn(async move {
+            let mut config = self.config.clone();
+            let mut port_map = self.router.watch_port_map();
+
+            loop {
+                let changed = tokio::select! {
+                    res = config.changed() => res,
+                    res = port_map.changed() => res,
+                };
+
+                if changed.is_err() {
+                    return;
+                }
+
+                self.reconcile().await;
+            }
+        }));
+    }
+
+    /// Ports currently being listened on, in order
+    pub fn bound_ports(&self) -> Vec<u16> {
+        let mut ports: Vec<u16> = self.listeners.lock().keys().copied().collect();
+        ports.sort_unstable();
+        ports
+    }
+
+    /// Closes every listener for good. Connections that were already accepted carry on.
+    pub async fn stop_accepting(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+
+        if let Some(reconciler) = self.reconciler.lock().take() {
+            reconciler.abort();
+        }
+
+        let stopped: Vec<RunningListener> = self.listeners.lock().drain().map(|(_, running)| running).collect();
+        for running in stopped {
+            running.handle.abort();
+            let _ = running.handle.await;
+            info!("Stopped listening on {}", running.addr);
         }
     }
 
-    async fn start_listener(&self, port: u16) {
-        loop {
-            let listener = TcpListener::bind(format!("{}:{}", self.config.listen_addr, port)).await.expect("tcp listener failed to bind");
+    // Listeners from the config, plus one per service port if dynamic listeners are enabled
+    fn desired_listeners(&self, config: &Config) -> HashMap<u16, ListenerConfig> {
+        let mut desired: HashMap<u16, ListenerConfig> = config.listeners.iter()
+            .map(|listener| (listener.port, listener.clone()))
+            .collect();
 
-            while let Ok((inbound, _)) = listener.accept().await {
-                let (dest_addr, dest_port) = self.router.get_destination(port).unwrap();
+        if !config.dynamic_listeners.enabled {
+            return desired;
+        }
 
-                tokio::spawn(async move {
-                    if let Err(e) = Self::proxy(inbound, format!("{}:{}", dest_addr, dest_port)).await {
-                        panic!("{}", e);
-                    }
-                });
+        // Only TCP is proxied
+        let port_map = self.router.watch_port_map().borrow().clone();
+        for service_port in port_map.ports(Protocol::Tcp) {
+            match config.dynamic_listeners.listen_port(service_port) {
+                // Explicitly configured listeners take precedence
+                Some(port) if desired.contains_key(&port) => {}
+                Some(port) if config.admin.port() == Some(port) => {
+                    error!("Service port {} maps to listen port {}, which the admin server uses", service_port.port, port);
+                }
+                Some(port) => {
+                    desired.insert(port, ListenerConfig {
+                        service_port: Some(PortRef::Number(service_port.port)),
+                        ..ListenerConfig::new(port)
+                    });
+                }
+                None => warn!("Service port {} does not map to a valid listen port with offset {}", service_port.port, config.dynamic_listeners.offset),
             }
         }
+
+        desired
     }
 
-    async fn proxy(mut inbound: TcpStream, proxy_addr: String) -> Result<()> {
-        let mut outbound = TcpStream::connect(proxy_addr).await.map_err(NodeBalancerError::IOError)?;
+    // Brings the bound listeners in line with the config and the service's ports. Only the accept loops are
+    // stopped, connections that were already accepted run in their own tasks and are left alone.
+    async fn reconcile(self: &Arc<Self>) {
+        if self.stopped.load(Ordering::SeqCst) {
+            return;
+        }
 
-        let (mut ri, mut wi) = inbound.split();
-        let (mut ro, mut wo) = outbound.split();
+        let config = self.config();
+        let desired = self.desired_listeners(&config);
 
-        let client_to_server = async {
-            io::copy(&mut ri, &mut wo).await.map_err(NodeBalancerError::IOError)?;
-            wo.shutdown().await
+        let desired: HashMap<u16, (SocketAddr, ListenerConfig)> = desired.into_iter()
+            .filter_map(|(port, listener)| match listener.listen_addr(&config).parse::<IpAddr>() {
+                Ok(ip) => Some((port, (SocketAddr::new(ip, port), listener))),
+                Err(_) => {
+                    error!("Listener {} has invalid listen address {}", port, listener.listen_addr(&config));
+                    None
+                }
+            })
+            .collect();
+
+        let stopped: Vec<JoinHandle<()>> = {
+            let mut listeners = self.listeners.lock();
+            let ports: Vec<u16> = listeners.iter()
+                .filter(|(port, running)| match desired.get(*port) {
+                    Some((addr, _)) => running.addr != *addr || running.dual_stack != config.dual_stack,
+                    None => true,
+                })
+                .map(|(port, _)| *port)
+                .collect();
+
+            ports.into_iter()
+                .filter_map(|port| listeners.remove(&port))
+                .map(|running| {
+                    info!("Stopped listening on {}", running.addr);
+                    running.handle.abort();
+                    running.handle
+                })
+                .collect()
         };
 
-        let server_to_client = async {
-            io::copy(&mut ro, &mut wi).await.map_err(NodeBalancerError::IOError)?;
-            wi.shutdown().await
+        // Wait for the sockets to be dropped, in case the same port is about to be bound on a new address
+        for handle in stopped {
+            let _ = handle.await;
+        }
+
+        for (addr, listener) in desired.into_values() {
+            if let Some(running) = self.listeners.lock().get_mut(&listener.port) {
+                running.config = listener;
+                continue;
+            }
+
+            match bind::bind(addr, config.dual_stack) {
+                Ok(tcp_listener) => {
+                    info!("Listening on {}", addr);
+
+                    // Held across the spawn so the accept loop can't look itself up before it's registered
+                    let mut listeners = self.listeners.lock();
+                    let handle = tokio::spawn(Arc::clone(self).accept_loop(tcp_listener, listener.port));
+                    listeners.insert(listener.port, RunningListener {
+                        addr,
+                        dual_stack: config.dual_stack,
+                        config: listener,
+                        handle,
+                    });
+                }
+                Err(e) => error!("Failed to bind listener on {}: {}", addr, e),
+            }
+        }
+    }
+
+    async fn accept_loop(self: Arc<Self>, listener: TcpListener, port: u16) {
+        let mut error_delay = MIN_ACCEPT_ERROR_DELAY;
+        let mut errors_since_logged = 0u64;
+        let mut last_logged: Option<Instant> = None;
+
+        loop {
+            let (inbound, client_addr) = match listener.accept().await {
+                Ok(v) => {
+                    error_delay = MIN_ACCEPT_ERROR_DELAY;
+                    v
+                }
+                // Usually out of file descriptors, which retrying straight away won't fix. Backing off stops the
+                // loop spinning and the log is limited so it doesn't flood while the limit is hit.
+                Err(e) => {
+                    errors_since_logged += 1;
+                    if last_logged.is_none_or(|at| at.elapsed() >= ACCEPT_ERROR_LOG_INTERVAL) {
+                        error!("Error accepting connection on port {}: {} ({} errors since last logged)", port, e, errors_since_logged);
+                        errors_since_logged = 0;
+                        last_logged = Some(Instant::now());
+                    }
+
+                    tokio::time::sleep(error_delay).await;
+                    error_delay = (error_delay * 2).min(MAX_ACCEPT_ERROR_DELAY);
+                    continue;
+                }
+            };
+            let accepted = Instant::now();
+
+            // Read the config per connection so reloaded strategies and timeouts apply straight away
+            let config = self.config();
+            let listener_config = match self.listeners.lock().get(&port) {
+                Some(running) => running.config.clone(),
+                None => continue, // Listener is being removed
+            };
+
+            // Before the limits, so refused clients don't use up any of them
+            let source_ranges = listener_config.source_ranges(&config);
+            let service_ranges = if source_ranges.from_service { self.router.service_source_ranges() } else { Vec::new() };
+            if !source_ranges.allows(client_addr.ip(), &service_ranges) {
+                Self::reject(client_addr, port, "source_range", "client IP is not allowed");
+                continue;
+            }
+
+            // Checked before spawning so rejected connections cost as little as possible
+            let listener_max = listener_config.max_connections(&config);
+            let permit = match self.limiter.try_acquire(&config.limits, port, listener_max, client_addr.ip()) {
+                Ok(permit) => Some(permit),
+                Err(_) if config.limits.over_limit == OverLimit::Queue => None,
+                Err(e) => {
+                    Self::reject(client_addr, port, e.reason(), e);
+                    continue;
+                }
+            };
+
+            let span = info_span!(
+                "connection",
+                id = self.next_connection_id.fetch_add(1, Ordering::Relaxed),
+                client = %client_addr,
+                port,
+                pod = field::Empty,
+                node = field::Empty,
+                destination = field::Empty,
+                reason = field::Empty,
+            );
+
+            let proxy = Arc::clone(&self);
+            tokio::spawn(async move {
+                let permit = match permit {
+                    Some(permit) => permit,
+                    None => match proxy.limiter.acquire(&config.limits, port, listener_max, client_addr.ip()).await {
+                        Ok(permit) => permit,
+                        Err(e) => return Self::reject(client_addr, port, e.reason(), e),
+                    },
+                };
+
+                let connection = AcceptedConnection::new(client_addr, port, accepted, config, listener_config, permit);
+
+                #[cfg(all(feature = "io-uring", target_os = "linux"))]
+                if let Some(workers) = &proxy.uring {
+                    return workers.dispatch(inbound, connection);
+                }
+
+                proxy.handle(inbound, connection).await;
+            }.instrument(span));
+        }
+    }
+
+    fn reject(client_addr: SocketAddr, port: u16, reason: &str, detail: impl std::fmt::Display) {
+        debug!("Rejected connection from {} on port {}: {}", client_addr, port, detail);
+        metrics().rejected_connections.with_label_values(&[&port.to_string(), reason]).inc();
+    }
+
+    async fn handle(&self, inbound: TcpStream, mut connection: AcceptedConnection) {
+        let (reason, error) = self.route(inbound, &mut connection).await;
+        connection.closed(reason, error);
+    }
+
+    async fn route(&self, mut inbound: TcpStream, connection: &mut AcceptedConnection) -> (CloseReason, Option<NodeBalancerError>) {
+        let (mut outbound, guard) = match connection.open(&self.router, TcpStream::connect).await {
+            Ok(v) => v,
+            Err((reason, e)) => return (reason, Some(e)),
         };
 
-        tokio::try_join!(client_to_server, server_to_client).map_err(NodeBalancerError::IOError)?;
+        let outcome = copy::copy_bidirectional(&mut inbound, &mut outbound, &connection.copy_options(), guard.closed()).await;
+        connection.copied(outcome)
+    }
+
+    /// Resolves the destination's addresses and connects to the first one that answers using `connect`
+    pub(super) async fn connect<S, F, Fut>(router: &Router, destination: &Destination, client: IpAddr, address_family: &AddressFamily, timeouts: &Timeouts, connect: F) -> Result<(S, SocketAddr)>
+    where
+        F: Fn(SocketAddr) -> Fut,
+        Fut: Future<Output = io::Result<S>>,
+    {
+        let mut ips = Vec::new();
+        for address in &destination.addresses {
+            match router.resolve(address).await {
+                Ok(resolved) => ips.extend(resolved),
+                Err(e) => warn!("Skipping address {} of node {}: {}", address, destination.node, e),
+            }
+        }
+
+        // Shuffled first so connections spread over addresses of the same family
+        ips.shuffle(&mut rand::thread_rng());
+        let addrs: Vec<SocketAddr> = address_family.order(ips, client).into_iter()
+            .map(|ip| SocketAddr::new(ip, destination.port))
+            .collect();
 
-        Ok(())
+        if addrs.is_empty() {
+            return NodeBalancerError::NoAddressesAvailable(destination.node.clone()).into();
+        }
+
+        let delay = timeouts.happy_eyeballs_delay.unwrap_or(Duration::from_millis(250));
+        let connect = connect::happy_eyeballs(&addrs, delay, connect);
+
+        match timeouts.connect {
+            Some(timeout) => tokio::time::timeout(timeout, connect).await
+                .map_err(|_| NodeBalancerError::ConnectTimeout(format!("node {} on port {}", destination.node, destination.port)))?,
+            None => connect.await,
+        }.map_err(NodeBalancerError::IOError)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,91 @@
+use crate::proxy::CloseReason;
+use crate::proxy::copy::{Activity, Direction};
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use tokio::io::{AsyncWriteExt, Interest};
+use tokio::net::tcp::{ReadHalf, WriteHalf};
+
+// Bytes moved per splice call, the default pipe capacity
+const CHUNK: usize = 64 * 1024;
+
+pub struct Pipe {
+    read: RawFd,
+    write: RawFd,
+}
+
+impl Pipe {
+    pub fn new() -> io::Result<Pipe> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Pipe {
+            read: fds[0],
+            write: fds[1],
+        })
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+fn splice(fd_in: RawFd, fd_out: RawFd, len: usize) -> io::Result<usize> {
+    let n = unsafe { libc::splice(fd_in, ptr::null_mut(), fd_out, ptr::null_mut(), len, libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK) };
+    if n < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(n as usize)
+    }
+}
+
+/// Moves bytes from `reader` to `writer` through `pipe` until EOF, then shuts down the writer. Returns false,
+/// having moved nothing, if the kernel can't splice these sockets so the caller can copy instead.
+pub async fn splice_one(reader: &ReadHalf<'_>, writer: &mut WriteHalf<'_>, pipe: &Pipe, activity: &Activity, direction: Direction) -> Result<bool, (CloseReason, io::Error)> {
+    let (read_error, write_error) = (direction.read_error(), direction.write_error());
+    let source = reader.as_ref();
+    let mut moved_any = false;
+
+    loop {
+        // The pipe is always empty here, so WouldBlock can only mean the socket has nothing to read
+        let n = loop {
+            source.readable().await.map_err(|e| (read_error, e))?;
+
+            match source.try_io(Interest::READABLE, || splice(source.as_raw_fd(), pipe.write, CHUNK)) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) if !moved_any && e.raw_os_error() == Some(libc::EINVAL) => return Ok(false),
+                Err(e) => return Err((read_error, e)),
+            }
+        };
+
+        if n == 0 {
+            writer.shutdown().await.map_err(|e| (write_error, e))?;
+            return Ok(true);
+        }
+
+        moved_any = true;
+        activity.touch();
+
+        let sink = writer.as_ref();
+        let mut pending = n;
+        while pending > 0 {
+            sink.writable().await.map_err(|e| (write_error, e))?;
+
+            match sink.try_io(Interest::WRITABLE, || splice(pipe.read, sink.as_raw_fd(), pending)) {
+                Ok(n) => pending -= n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err((write_error, e)),
+            }
+        }
+
+        activity.moved(direction, n);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::config::IoUringConfig;
+use crate::proxy::{AcceptedConnection, CloseReason};
+use crate::proxy::copy::{self, Activity, CopyOptions, CopyOutcome, Direction, DirectionResult};
+use crate::router::Router;
+use crate::NodeBalancerError;
+use std::cell::RefCell;
+use std::future::Future;
+use std::io;
+use std::net::Shutdown;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::mpsc;
+use tokio_uring::buf::IoBuf;
+use tokio_uring::net::TcpStream;
+use tracing::{error, Instrument};
+
+// Most buffers a worker keeps around for reuse
+const MAX_POOLED_BUFFERS: usize = 4096;
+
+struct Job {
+    inbound: std::net::TcpStream,
+    connection: AcceptedConnection,
+}
+
+/// Threads that each run an io_uring runtime, connecting to nodes and moving bytes for the connections
+/// they are handed. Accepting, the source ranges and the limits stay on the main runtime: tokio-uring 0.4
+/// can't accept on a listener it didn't bind, and has no fixed buffer reads or writes, so the buffers are
+/// plain ones from [BufferPool] rather than registered with the ring.
+pub struct UringWorkers {
+    workers: Vec<mpsc::UnboundedSender<Job>>,
+    next: AtomicUsize,
+}
+
+impl UringWorkers {
+    /// Starts the workers, failing if the kernel can't set up a ring
+    pub fn start(config: &IoUringConfig, router: Arc<Router>) -> io::Result<UringWorkers> {
+        let mut workers = Vec::with_capacity(config.workers);
+
+        for i in 0..config.workers {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let (started_tx, started_rx) = std::sync::mpsc::channel();
+            let router = Arc::clone(&router);
+            let entries = config.entries;
+            let buffer_size = config.buffer_size;
+
+            std::thread::Builder::new()
+                .name(format!("io-uring-{}", i))
+                .spawn(move || {
+                    let runtime = match tokio_uring::Runtime::new(tokio_uring::builder().entries(entries)) {
+                        Ok(runtime) => runtime,
+                        Err(e) => {
+                            let _ = started_tx.send(Err(e));
+                            return;
+                        }
+                    };
+
+                    let _ = started_tx.send(Ok(()));
+                    runtime.block_on(run(rx, router, BufferPool::new(buffer_size)));
+                })?;
+
+            started_rx.recv().map_err(|_| io::Error::other("io_uring worker exited while starting"))??;
+            workers.push(tx);
+        }
+
+        Ok(UringWorkers {
+            workers,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Hands the connection to the next worker
+    pub fn dispatch(&self, inbound: tokio::net::TcpStream, connection: AcceptedConnection) {
+        let inbound = match inbound.into_std() {
+            Ok(v) => v,
+            Err(e) => return connection.closed(CloseReason::ClientError, Some(NodeBalancerError::IOError(e))),
+        };
+
+        let worker = &self.workers[self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len()];
+        if let Err(mpsc::error::SendError(job)) = worker.send(Job { inbound, connection }) {
+            error!("io_uring worker has stopped, dropping connection from {}", job.connection.client_addr);
+        }
+    }
+}
+
+async fn run(mut jobs: mpsc::UnboundedReceiver<Job>, router: Arc<Router>, buffers: Rc<BufferPool>) {
+    while let Some(job) = jobs.recv().await {
+        let router = Arc::clone(&router);
+        let buffers = Rc::clone(&buffers);
+        let span = job.connection.span.clone();
+
+        tokio_uring::spawn(async move {
+            let mut connection = job.connection;
+            let (reason, error) = route(&router, job.inbound, &mut connection, &buffers).await;
+            connection.closed(reason, error);
+        }.instrument(span));
+    }
+}
+
+async fn route(router: &Router, inbound: std::net::TcpStream, connection: &mut AcceptedConnection, buffers: &BufferPool) -> (CloseReason, Option<NodeBalancerError>) {
+    let (outbound, guard) = match connection.open(router, TcpStream::connect).await {
+        Ok(v) => v,
+        Err((reason, e)) => return (reason, Some(e)),
+    };
+
+    let inbound = TcpStream::from_std(inbound);
+    let outcome = copy_bidirectional(&inbound, &outbound, buffers, &connection.copy_options(), guard.closed()).await;
+    connection.copied(outcome)
+}
+
+/// Same as the epoll copy, with reads and writes submitted to the ring. The data path option is ignored.
+pub async fn copy_bidirectional<F: Future<Output = ()>>(inbound: &TcpStream, outbound: &TcpStream, buffers: &BufferPool, options: &CopyOptions, closed: F) -> CopyOutcome {
+    let activity = Activity::new();
+
+    let client_to_server = copy_one(inbound, outbound, buffers, &activity, Direction::FromClient);
+    let server_to_client = copy_one(outbound, inbound, buffers, &activity, Direction::FromBackend);
+
+    copy::supervise(client_to_server, server_to_client, &activity, options, closed).await
+}
+
+async fn copy_one(reader: &TcpStream, writer: &TcpStream, buffers: &BufferPool, activity: &Activity, direction: Direction) -> DirectionResult {
+    let mut buf = buffers.take();
+
+    let result = loop {
+        // Reads fill the spare capacity, so start each one empty
+        buf.clear();
+
+        let (res, read) = reader.read(buf).await;
+        buf = read;
+
+        let n = match res {
+            Ok(0) => break writer.shutdown(Shutdown::Write).map_err(|e| (direction.write_error(), e)),
+            Ok(n) => n,
+            Err(e) => break Err((direction.read_error(), e)),
+        };
+
+        activity.touch();
+
+        let (res, written) = writer.write_all(buf.slice(..n)).await;
+        buf = written.into_inner();
+
+        if let Err(e) = res {
+            break Err((direction.write_error(), e));
+        }
+
+        activity.moved(direction, n);
+    };
+
+    buffers.give(buf);
+    result
+}
+
+/// Buffers for one worker's connections, so busy workers don't allocate for every connection
+pub struct BufferPool {
+    size: usize,
+    free: RefCell<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    pub fn new(size: usize) -> Rc<BufferPool> {
+        Rc::new(BufferPool {
+            size,
+            free: RefCell::new(Vec::new()),
+        })
+    }
+
+    fn take(&self) -> Vec<u8> {
+        self.free.borrow_mut().pop().unwrap_or_else(|| Vec::with_capacity(self.size))
+    }
+
+    fn give(&self, buf: Vec<u8>) {
+        let mut free = self.free.borrow_mut();
+        if free.len() < MAX_POOLED_BUFFERS && buf.capacity() == self.size {
+            free.push(buf);
+        }
+    }
+}
@@ -1,12 +1,107 @@
+use crate::router::NodeAddress;
+use crate::config::{NodeAddressConfig, NodeWeightsConfig};
+use std::convert::TryFrom;
+use std::collections::BTreeMap;
+
+// Highest weight a node can have, so the weights of up to 65537 nodes add up without overflowing a u32
+const MAX_WEIGHT: u32 = u16::MAX as u32;
+
 #[derive(Clone, Debug)]
 pub struct AddressableNode {
-    pub addresses: Vec<String>,
+    // (type, address) as published in the node's status
+    pub addresses: Vec<(String, String)>,
+    pub annotations: BTreeMap<String, String>,
+    pub zone: Option<String>,
+    pub region: Option<String>,
 }
 
 impl AddressableNode {
-    pub fn new(addresses: Vec<String>) -> AddressableNode {
+    pub fn new(addresses: Vec<(String, String)>, annotations: BTreeMap<String, String>, labels: &BTreeMap<String, String>) -> AddressableNode {
         AddressableNode {
             addresses,
+            annotations,
+            zone: Self::topology_label(labels, "zone"),
+            region: Self::topology_label(labels, "region"),
         }
     }
+
+    // Older clusters only set the deprecated failure-domain labels
+    fn topology_label(labels: &BTreeMap<String, String>, key: &str) -> Option<String> {
+        labels.get(&format!("topology.kubernetes.io/{}", key))
+            .or_else(|| labels.get(&format!("failure-domain.beta.kubernetes.io/{}", key)))
+            .cloned()
+    }
+
+    /// This node's share of connections relative to other nodes: the weight annotation if it's set, otherwise
+    /// how many routable pods it hosts, capped at max_pods, or empty_node_weight if it hosts none. Never more
+    /// than MAX_WEIGHT.
+    pub fn weight(&self, pods: usize, config: &NodeWeightsConfig) -> u32 {
+        self.unclamped_weight(pods, config).min(MAX_WEIGHT)
+    }
+
+    fn unclamped_weight(&self, pods: usize, config: &NodeWeightsConfig) -> u32 {
+        // Anything but a whole number is ignored
+        let annotated = Some(&config.annotation)
+            .filter(|annotation| !annotation.is_empty())
+            .and_then(|annotation| self.annotations.get(annotation))
+            .and_then(|weight| weight.trim().parse().ok());
+
+        if let Some(weight) = annotated {
+            return weight;
+        }
+
+        if pods == 0 {
+            return config.empty_node_weight;
+        }
+
+        let pods = u32::try_from(pods).unwrap_or(u32::MAX);
+        config.max_pods.map_or(pods, |max_pods| pods.min(max_pods))
+    }
+
+    /// Addresses to route to: the override annotation if set, otherwise the first address type in the
+    /// preference list that the node publishes
+    pub fn select_addresses(&self, config: &NodeAddressConfig) -> Vec<NodeAddress> {
+        if !config.annotation.is_empty() {
+            if let Some(value) = self.annotations.get(&config.annotation) {
+                let overridden: Vec<NodeAddress> = value.split(',')
+                    .map(str::trim)
+                    .filter(|address| !address.is_empty())
+                    .map(NodeAddress::parse)
+                    .collect();
+
+                if !overridden.is_empty() {
+                    return overridden;
+                }
+            }
+        }
+
+        config.preference.iter()
+            .map(|wanted| {
+                self.addresses.iter()
+                    .filter(|(type_, _)| type_ == wanted)
+                    .map(|(_, address)| NodeAddress::parse(address))
+                    .collect::<Vec<NodeAddress>>()
+            })
+            .find(|addresses| !addresses.is_empty())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(weight: &str) -> AddressableNode {
+        let annotations = vec![("node-balancer.io/weight".to_owned(), weight.to_owned())].into_iter().collect();
+        AddressableNode::new(Vec::new(), annotations, &BTreeMap::new())
+    }
+
+    #[test]
+    fn annotated_weight_is_clamped() {
+        let config = NodeWeightsConfig::default();
+
+        assert_eq!(node("3").weight(1, &config), 3);
+        assert_eq!(node("4294967295").weight(1, &config), MAX_WEIGHT);
+        assert_eq!(node("heavy").weight(2, &config), 2);
+    }
 }
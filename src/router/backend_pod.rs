@@ -2,13 +2,24 @@
 pub struct BackendPod {
     pub node: String,
     pub is_service_backend: bool,
+    // Its Ready condition is True, the same pods kube-proxy forwards to
+    pub ready: bool,
+    // Has a deletion timestamp, so should be drained rather than sent new connections
+    pub terminating: bool,
 }
 
 impl BackendPod {
-    pub fn new(node: String, is_service_backend: bool) -> BackendPod {
+    pub fn new(node: String, is_service_backend: bool, ready: bool, terminating: bool) -> BackendPod {
         BackendPod {
             node,
             is_service_backend,
+            ready,
+            terminating,
         }
     }
+
+    /// Whether new connections may be routed to it
+    pub fn routable(&self) -> bool {
+        self.is_service_backend && self.ready && !self.terminating
+    }
 }
@@ -1,17 +1,26 @@
-use crate::router::PortMap;
+use crate::router::{PortMap, TrafficPolicy};
+use ipnet::IpNet;
 use std::collections::BTreeMap;
 
 #[derive(Clone, Debug)]
 pub struct BalancedService {
     pub selector: BTreeMap<String, String>,
     pub port_map: PortMap,
+    // spec.loadBalancerSourceRanges
+    pub source_ranges: Vec<IpNet>,
+    pub traffic_policy: TrafficPolicy,
+    // Where kube-proxy reports whether a node has ready pods, only allocated for Local LoadBalancer services
+    pub health_check_node_port: Option<u16>,
 }
 
 impl BalancedService {
-    pub fn new(selector: BTreeMap<String, String>, port_map: PortMap) -> BalancedService {
+    pub fn new(selector: BTreeMap<String, String>, port_map: PortMap, source_ranges: Vec<IpNet>, traffic_policy: TrafficPolicy, health_check_node_port: Option<u16>) -> BalancedService {
         BalancedService {
             selector,
             port_map,
+            source_ranges,
+            traffic_policy,
+            health_check_node_port,
         }
     }
 }
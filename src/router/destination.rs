@@ -0,0 +1,22 @@
+use crate::router::NodeAddress;
+
+#[derive(Clone, Debug)]
+pub struct Destination {
+    // None when the node hosts no pods of the service and forwards the connection on itself
+    pub pod: Option<String>,
+    pub node: String,
+    // Every address selected for the node, the proxy picks between them when connecting
+    pub addresses: Vec<NodeAddress>,
+    pub port: u16,
+}
+
+impl Destination {
+    pub fn new(pod: Option<String>, node: String, addresses: Vec<NodeAddress>, port: u16) -> Destination {
+        Destination {
+            pod,
+            node,
+            addresses,
+            port,
+        }
+    }
+}
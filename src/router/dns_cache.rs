@@ -0,0 +1,50 @@
+use crate::{Result, NodeBalancerError};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+pub struct DnsCache {
+    // hostname -> (expiry, addresses)
+    entries: RwLock<HashMap<String, (Instant, Vec<IpAddr>)>>,
+}
+
+impl DnsCache {
+    pub fn new() -> DnsCache {
+        DnsCache {
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub async fn resolve(&self, hostname: &str, ttl: Duration) -> Result<Vec<IpAddr>> {
+        if let Some((expires, addresses)) = self.entries.read().get(hostname) {
+            if *expires > Instant::now() {
+                return Ok(addresses.clone());
+            }
+        }
+
+        let addresses: Vec<IpAddr> = tokio::net::lookup_host((hostname, 0)).await
+            .map_err(|e| NodeBalancerError::DnsError(hostname.to_owned(), e))?
+            .map(|addr| addr.ip())
+            .collect();
+
+        if addresses.is_empty() {
+            return NodeBalancerError::NoAddressesAvailable(hostname.to_owned()).into();
+        }
+
+        debug!("Resolved {} to {:?}", hostname, addresses);
+
+        let mut entries = self.entries.write();
+        entries.retain(|_, (expires, _)| *expires > Instant::now());
+        entries.insert(hostname.to_owned(), (Instant::now() + ttl, addresses.clone()));
+
+        Ok(addresses)
+    }
+}
+
+impl Default for DnsCache {
+    fn default() -> DnsCache {
+        DnsCache::new()
+    }
+}
@@ -0,0 +1,110 @@
+use crate::router::{Router, NodeAddress, Protocol, TrafficPolicy};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use crate::config::HealthCheckConfig;
+use hyper::{Client, StatusCode, Uri};
+use hyper::client::HttpConnector;
+use tokio::net::TcpStream;
+use futures::future::join_all;
+use tracing::{info, warn};
+
+#[derive(Clone, Copy)]
+enum Probe {
+    // Connect to this NodePort
+    NodePort(u16),
+    // GET /healthz on this port, which kube-proxy answers with 200 while the node has ready pods
+    HealthCheckNodePort(u16),
+}
+
+impl Router {
+    pub(super) async fn run_health_checks(&self) {
+        loop {
+            let health_check = self.config().health_check.clone();
+
+            if health_check.enabled {
+                self.check_nodes(&health_check).await;
+            } else if !self.node_health.read().is_empty() {
+                // Forget everything so that re-enabling starts from a clean slate
+                self.node_health.write().clear();
+            }
+
+            tokio::time::sleep(health_check.interval).await;
+        }
+    }
+
+    // Probes one NodePort of the service on every node. This goes through kube-proxy, so it catches
+    // nodes that are up but can't forward traffic. For Local services the healthCheckNodePort is probed
+    // instead, on the nodes hosting pods as the rest are never routed to.
+    async fn check_nodes(&self, health_check: &HealthCheckConfig) {
+        let probe = match self.service.read().as_ref() {
+            Some(svc) => match svc.health_check_node_port {
+                Some(port) if svc.traffic_policy == TrafficPolicy::Local && health_check.health_check_node_port => Probe::HealthCheckNodePort(port),
+                _ => match svc.port_map.ports(Protocol::Tcp).next() {
+                    Some(port) => Probe::NodePort(port.node_port),
+                    None => return,
+                },
+            },
+            None => return,
+        };
+
+        let hosting: Option<HashSet<String>> = match probe {
+            Probe::HealthCheckNodePort(_) => Some(self.pod_nodes()),
+            Probe::NodePort(_) => None,
+        };
+
+        let address_config = self.config().node_addresses.clone();
+        let targets: Vec<(String, NodeAddress)> = self.nodes.read().iter()
+            .filter(|(name, _)| hosting.as_ref().is_none_or(|hosting| hosting.contains(*name)))
+            .filter_map(|(name, node)| node.select_addresses(&address_config).into_iter().next().map(|address| (name.clone(), address)))
+            .collect();
+
+        let client = Client::new();
+        let probes = targets.into_iter().map(|(name, address)| {
+            let client = &client;
+
+            async move {
+                let probe = async {
+                    let ip = *self.resolve(&address).await.ok()?.first()?;
+
+                    match probe {
+                        Probe::NodePort(port) => TcpStream::connect(SocketAddr::new(ip, port)).await.ok().map(|_| true),
+                        Probe::HealthCheckNodePort(port) => Some(Self::probe_health_check_node_port(client, SocketAddr::new(ip, port)).await),
+                    }
+                };
+
+                let success = matches!(tokio::time::timeout(health_check.timeout, probe).await, Ok(Some(true)));
+                (name, success)
+            }
+        });
+
+        let results = join_all(probes).await;
+
+        let mut node_health = self.node_health.write();
+        node_health.retain(|name, _| results.iter().any(|(probed, _)| probed == name));
+
+        for (name, success) in results {
+            let health = node_health.entry(name.clone()).or_default();
+            if health.record(success, health_check.healthy_threshold, health_check.unhealthy_threshold) {
+                if health.healthy {
+                    info!("Node {} passed health checks, routing to it again", name);
+                } else {
+                    warn!("Node {} failed {} health checks, no longer routing to it", name, health_check.unhealthy_threshold);
+                }
+            }
+        }
+    }
+
+    async fn probe_health_check_node_port(client: &Client<HttpConnector>, addr: SocketAddr) -> bool {
+        let uri: Uri = match format!("http://{}/healthz", addr).parse() {
+            Ok(uri) => uri,
+            Err(_) => return false,
+        };
+
+        client.get(uri).await.is_ok_and(|response| response.status() == StatusCode::OK)
+    }
+
+    // Nodes hosting routable pods
+    fn pod_nodes(&self) -> HashSet<String> {
+        Self::routable_nodes(&self.pod_names.read(), &self.pods.read())
+    }
+}
@@ -0,0 +1,20 @@
+use crate::router::Protocol;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MappedPort {
+    pub name: Option<String>,
+    pub protocol: Protocol,
+    pub port: u16,
+    pub node_port: u16,
+}
+
+impl MappedPort {
+    pub fn new(name: Option<String>, protocol: Protocol, port: u16, node_port: u16) -> MappedPort {
+        MappedPort {
+            name,
+            protocol,
+            port,
+            node_port,
+        }
+    }
+}
@@ -1,18 +1,41 @@
+#[allow(clippy::module_inception)]
 mod router;
 pub use router::Router;
 
 mod parse_nodes;
 mod parse_services;
 mod parse_pods;
+mod health_check;
 
 mod addressable_node;
 pub use addressable_node::AddressableNode;
 
+mod destination;
+pub use destination::Destination;
+
+mod node_address;
+pub use node_address::NodeAddress;
+
+mod dns_cache;
+pub use dns_cache::DnsCache;
+
 mod port_map;
 pub use port_map::PortMap;
 
+mod mapped_port;
+pub use mapped_port::MappedPort;
+
+mod protocol;
+pub use protocol::Protocol;
+
+mod traffic_policy;
+pub use traffic_policy::TrafficPolicy;
+
 mod balanced_service;
 pub use balanced_service::BalancedService;
 
 mod backend_pod;
-pub use backend_pod::BackendPod;
\ No newline at end of file
+pub use backend_pod::BackendPod;
+
+mod node_health;
+pub use node_health::NodeHealth;
\ No newline at end of file
@@ -0,0 +1,27 @@
+use std::fmt;
+use std::net::IpAddr;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NodeAddress {
+    Ip(IpAddr),
+    // Resolved through the router's DNS cache when connecting
+    Hostname(String),
+}
+
+impl NodeAddress {
+    pub fn parse(address: &str) -> NodeAddress {
+        match address.parse() {
+            Ok(ip) => NodeAddress::Ip(ip),
+            Err(_) => NodeAddress::Hostname(address.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for NodeAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeAddress::Ip(ip) => write!(f, "{}", ip),
+            NodeAddress::Hostname(hostname) => write!(f, "{}", hostname),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+#[derive(Clone, Debug)]
+pub struct NodeHealth {
+    pub healthy: bool,
+    // Consecutive probes that disagreed with the current state
+    pub streak: u32,
+}
+
+impl NodeHealth {
+    pub fn new() -> NodeHealth {
+        NodeHealth {
+            healthy: true,
+            streak: 0,
+        }
+    }
+
+    /// Records a probe result, returning true if the node changed state
+    pub fn record(&mut self, success: bool, healthy_threshold: u32, unhealthy_threshold: u32) -> bool {
+        if success == self.healthy {
+            self.streak = 0;
+            return false;
+        }
+
+        self.streak += 1;
+
+        let threshold = if self.healthy { unhealthy_threshold } else { healthy_threshold };
+        if self.streak >= threshold {
+            self.healthy = success;
+            self.streak = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Default for NodeHealth {
+    fn default() -> NodeHealth {
+        NodeHealth::new()
+    }
+}
@@ -1,20 +1,15 @@
 use crate::router::{Router, AddressableNode};
-use crate::{Result, NodeBalancerError};
-use kube::Api;
+use crate::connections::Backend;
+use crate::Result;
 use k8s_openapi::api::core::v1::{Node, NodeStatus};
-use kube::api::ListParams;
-use kube_runtime::watcher;
 use futures_util::TryStreamExt;
 use kube_runtime::watcher::Event;
 use std::collections::HashMap;
-use log::info;
+use tracing::{info, instrument};
 
 impl Router {
     pub async fn fetch_nodes(&self) -> Result<HashMap<String, AddressableNode>> {
-        let node_api: Api<Node> = Api::all(self.client.clone());
-
-        let params = ListParams::default();
-        let nodes = node_api.list(&params).await.map_err(NodeBalancerError::KubeError)?.items;
+        let nodes = self.source.list_nodes().await?;
 
         Ok(Self::map_nodes(nodes))
     }
@@ -22,25 +17,26 @@ impl Router {
     fn map_nodes(nodes: Vec<Node>) -> HashMap<String, AddressableNode> {
         nodes.into_iter()
             .filter_map(|node| node.metadata.name.clone().map(|name| (node, name)))
-            .filter_map(|(node, name)| node.status.map(|status| (status, name)))
-            .map(|(status, name)| (name, AddressableNode::new(Self::extract_addresses(status))))
-            .filter(|(_, node)| !node.addresses.is_empty())
+            .filter_map(|(node, name)| {
+                let annotations = node.metadata.annotations;
+                let labels = node.metadata.labels;
+                node.status.map(|status| (name, AddressableNode::new(Self::extract_addresses(status), annotations, &labels)))
+            })
             .collect()
     }
 
+    #[instrument(skip(self))]
     pub async fn watch_nodes(&self) -> Result<()> {
-        let node_api: Api<Node> = Api::all(self.client.clone());
-        let params = ListParams::default();
-
-        let watcher = watcher(node_api, params);
-        watcher.try_for_each(|ev| async {
+        self.source.watch_nodes().try_for_each(|ev| async {
             match ev {
                 // Update or delete
                 Event::Applied(node) => {
                     Self::map_nodes(vec![node]).into_iter()
                         .for_each(|(name, addressable_node)| {
-                            info!("Got new node {} with addresses {:?}", name, addressable_node.addresses);
-                            self.nodes.write().insert(name, addressable_node);
+                            let zone = addressable_node.zone.as_deref().unwrap_or("unknown");
+                            info!("Got new node {} in zone {} with addresses {:?}", name, zone, addressable_node.addresses);
+                            self.nodes.write().insert(name.clone(), addressable_node);
+                            self.connections.cancel_drain(&Backend::Node(name));
                         });
                 }
 
@@ -49,6 +45,7 @@ impl Router {
                     if let Some(name) = node.metadata.name {
                         self.nodes.write().remove(&name);
                         info!("Deleted node {}", name);
+                        self.drain(Backend::Node(name));
                     }
                 }
 
@@ -56,20 +53,24 @@ impl Router {
                     info!("Got node stream restarted");
 
                     let nodes = Self::map_nodes(nodes);
+                    let removed: Vec<String> = self.nodes.read().keys()
+                        .filter(|name| !nodes.contains_key(*name))
+                        .cloned()
+                        .collect();
+
                     *self.nodes.write() = nodes;
+                    removed.into_iter().for_each(|name| self.drain(Backend::Node(name)));
                 }
             }
 
             Ok(())
-        }).await.map_err(NodeBalancerError::WatcherError)?;
-
-        Ok(())
+        }).await
     }
 
-    fn extract_addresses(status: NodeStatus) -> Vec<String> {
+    // Selecting which of these to use is left to get_destination, so the preference can change at runtime
+    fn extract_addresses(status: NodeStatus) -> Vec<(String, String)> {
         status.addresses.into_iter()
-            .filter(|address| address.type_ == "InternalIP")
-            .map(|address| address.address)
+            .map(|address| (address.type_, address.address))
             .collect()
     }
 }
\ No newline at end of file
@@ -1,80 +1,164 @@
-use crate::router::{Router, BackendPod};
-use crate::{Result, NodeBalancerError};
-use kube::Api;
+use crate::router::{Router, BackendPod, TrafficPolicy};
+use crate::connections::Backend;
+use crate::Result;
 use k8s_openapi::api::core::v1::Pod;
-use kube::api::ListParams;
-use std::collections::{BTreeMap, HashMap};
-use kube_runtime::watcher;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use futures_util::TryStreamExt;
 use kube_runtime::watcher::Event;
-use log::{info, warn};
+use tracing::{info, instrument, warn};
 
 impl Router {
     pub async fn fetch_pods(&self, selector: &BTreeMap<String, String>) -> Result<HashMap<String, BackendPod>> {
-        let pod_api: Api<Pod> = Api::all(self.client.clone());
-
-        let params = ListParams::default()
-            .labels(Self::build_selector(selector).as_str());
-
-        let pods = pod_api.list(&params).await.map_err(NodeBalancerError::KubeError)?.items;
+        let pods = self.source.list_pods(&self.config().service.namespace, selector).await?;
 
         // pod_name -> node_name
         Ok(self.map_pods(pods))
     }
 
+    // Pods in other namespaces are left out, pods are only known by name
     fn map_pods(&self, pods: Vec<Pod>) -> HashMap<String, BackendPod> {
         pods.into_iter()
+            .filter(|pod| self.in_service_namespace(pod))
             .filter_map(|pod| pod.metadata.name.clone().map(|name| (name, pod)))
-            .map(|(name, pod)| {
-                let matches = self.svc_matches(&pod);
-                (name, pod, matches)
+            .filter_map(|(name, pod)| {
+                let svc_matches = self.svc_matches(&pod);
+                let ready = Self::is_ready(&pod);
+                let terminating = pod.metadata.deletion_timestamp.is_some();
+                let node_name = pod.spec?.node_name?;
+
+                Some((name, BackendPod::new(node_name, svc_matches, ready, terminating)))
             })
-            .filter_map(|(name, pod, svc_matches)| pod.spec.map(|spec| (name, spec, svc_matches)))
-            .filter_map(|(name, spec, svc_matches)| spec.node_name.map(|node_name| (name, BackendPod::new(node_name, svc_matches))))
             .collect()
     }
 
-    pub async fn watch_pods(&self) -> Result<()> {
-        let svc_api: Api<Pod> = Api::all(self.client.clone());
-        let params = ListParams::default();
+    fn is_ready(pod: &Pod) -> bool {
+        pod.status.iter()
+            .flat_map(|status| &status.conditions)
+            .any(|condition| condition.type_ == "Ready" && condition.status == "True")
+    }
 
-        let watcher = watcher(svc_api, params);
-        watcher.try_for_each(|ev| async {
+    #[instrument(skip(self))]
+    pub async fn watch_pods(&self) -> Result<()> {
+        self.source.watch_pods().try_for_each(|ev| async {
             match ev {
                 // Update or delete
                 Event::Applied(pod) => {
                     self.map_pods(vec![pod]).into_iter()
-                        .for_each(|(name, backend_pod)| {
-                            let pod_names = self.pod_names.write();
-
-                            info!("Got new pod {}", name);
-                            self.pods.write().insert(name, backend_pod);
-
-                            // See comments in get_destination
-                            drop(pod_names);
-                        });
+                        .for_each(|(name, backend_pod)| self.apply_pod(name, backend_pod));
                 }
 
                 Event::Deleted(pod) => {
+                    if !self.in_service_namespace(&pod) {
+                        return Ok(());
+                    }
+
                     if let Some(name) = pod.metadata.name {
-                        self.pod_names.write().retain(|pod_name| pod_name != &name);
-                        self.pods.write().remove(&name);
+                        self.update_pods(|pod_names, pods| {
+                            pod_names.retain(|pod_name| pod_name != &name);
+                            pods.remove(&name);
+                        });
+
                         info!("Deleted pod {}", name);
                     }
                 }
 
-                Event::Restarted(_pods) => {
+                // Pods may have changed or gone while the watch was down
+                Event::Restarted(pods) => {
                     info!("Got pod stream restarted");
+
+                    let pods = if self.has_service() { self.map_pods(pods) } else { HashMap::new() };
+                    self.replace_pods(pods);
                 }
             }
 
             Ok(())
-        }).await.map_err(NodeBalancerError::WatcherError)?;
+        }).await
+    }
+
+    // Routes new connections to pods that back the service, are ready and aren't terminating, and stops routing
+    // to the ones that stopped being any of those
+    fn apply_pod(&self, name: String, backend_pod: BackendPod) {
+        let routable = backend_pod.routable();
+
+        self.update_pods(|pod_names, pods| {
+            let known = pod_names.contains(&name);
+
+            if backend_pod.is_service_backend {
+                pods.insert(name.clone(), backend_pod);
+            } else {
+                pods.remove(&name);
+            }
+
+            if routable && !known {
+                pod_names.push(name.clone());
+                info!("Got new pod {}", name);
+            } else if !routable && known {
+                pod_names.retain(|pod_name| pod_name != &name);
+                info!("Pod {} is no longer routable", name);
+            }
+        });
+    }
+
+    // Replaces every pod with the service's pods in `fetched`
+    pub(super) fn replace_pods(&self, fetched: HashMap<String, BackendPod>) {
+        self.update_pods(|pod_names, pods| {
+            pods.clear();
+            pod_names.clear();
+
+            for (name, pod) in fetched.into_iter().filter(|(_, pod)| pod.is_service_backend) {
+                if pod.routable() {
+                    pod_names.push(name.clone());
+                }
 
-        Ok(())
+                pods.insert(name, pod);
+            }
+        });
     }
 
+    // Changes the pods through `update`. With the Local policy a node left without routable pods has nothing
+    // for kube-proxy to forward to, so it's drained, and it stops draining once it has some again. With
+    // Cluster it still forwards to pods elsewhere.
+    fn update_pods<F: FnOnce(&mut Vec<String>, &mut HashMap<String, BackendPod>)>(&self, update: F) {
+        // See comments in get_destination
+        let mut pod_names = self.pod_names.write();
+        let mut pods = self.pods.write();
+
+        let before = Self::routable_nodes(&pod_names, &pods);
+        update(&mut pod_names, &mut pods);
+        let after = Self::routable_nodes(&pod_names, &pods);
+
+        drop(pods);
+        drop(pod_names);
+
+        let local = self.service.read().as_ref().is_some_and(|svc| svc.traffic_policy == TrafficPolicy::Local);
+        if local {
+            before.difference(&after).for_each(|node| self.drain(Backend::Node(node.clone())));
+        }
+
+        let nodes = self.nodes.read();
+        after.difference(&before)
+            .filter(|node| nodes.contains_key(*node))
+            .for_each(|node| self.connections.cancel_drain(&Backend::Node(node.clone())));
+    }
+
+    // Nodes hosting routable pods
+    pub(super) fn routable_nodes(pod_names: &[String], pods: &HashMap<String, BackendPod>) -> HashSet<String> {
+        pod_names.iter()
+            .filter_map(|pod_name| pods.get(pod_name))
+            .map(|pod| pod.node.clone())
+            .collect()
+    }
+
+    fn in_service_namespace(&self, pod: &Pod) -> bool {
+        pod.metadata.namespace.as_deref() == Some(&self.config().service.namespace)
+    }
+
+    // A service only selects pods in its own namespace
     fn svc_matches(&self, pod: &Pod) -> bool {
+        if !self.in_service_namespace(pod) {
+            return false;
+        }
+
         match &*self.service.read() {
             Some(svc) => {
                 for (key, value) in &svc.selector {
@@ -98,11 +182,4 @@ impl Router {
             }
         }
     }
-
-    fn build_selector(selector: &BTreeMap<String, String>) -> String {
-        selector.iter()
-            .map(|(key, value)| format!("{}={}", key, value))
-            .collect::<Vec<String>>()
-            .join(",")
-    }
 }
\ No newline at end of file
@@ -1,93 +1,132 @@
-use crate::router::{Router, PortMap, BalancedService};
+use crate::router::{Router, PortMap, BalancedService, MappedPort, Protocol, TrafficPolicy};
 use crate::{Result, NodeBalancerError};
-use kube::Api;
 use k8s_openapi::api::core::v1::{Service, ServicePort};
-use kube::api::ListParams;
-use kube_runtime::watcher;
 use futures_util::TryStreamExt;
 use kube_runtime::watcher::Event;
-use log::{error, info};
+use tracing::{error, info, instrument, warn};
+use std::sync::Arc;
+use ipnet::IpNet;
+use std::convert::TryFrom;
 
 impl Router {
     pub async fn fetch_service(&self) -> Result<BalancedService> {
-        let svc_api: Api<Service> = Api::namespaced(self.client.clone(), &self.config.service_namespace[..]);
-
-        let svc = svc_api.get(&self.config.service_name[..]).await.map_err(NodeBalancerError::KubeError)?;
-        Self::map_service(svc)
+        let service = self.config().service.clone();
+        let svc = self.source.get_service(&service.namespace, &service.name).await?;
+        self.map_service(svc)
     }
 
-    fn map_service(svc: Service) -> Result<BalancedService> {
+    fn map_service(&self, svc: Service) -> Result<BalancedService> {
         let spec = svc.spec.ok_or(NodeBalancerError::MissingSpec)?;
-        if spec.type_.as_deref() != Some("NodePort") {
-            return NodeBalancerError::WrongServiceType(spec.type_.as_deref().unwrap_or("None").to_owned()).into();
+        // LoadBalancer services get node ports too, unless allocating them is turned off
+        if !matches!(spec.type_.as_deref(), Some("NodePort") | Some("LoadBalancer")) {
+            let service_type = spec.type_.as_deref().unwrap_or("None");
+            self.events.service_warning(
+                &self.config().service,
+                "UnsupportedServiceType",
+                format!("service type {} is not supported, use NodePort or LoadBalancer", service_type),
+            );
+
+            return NodeBalancerError::WrongServiceType(service_type.to_owned()).into();
         }
 
-        Ok(BalancedService::new(spec.selector, Self::parse_port_map(spec.ports)))
-    }
+        let source_ranges = Self::parse_source_ranges(spec.load_balancer_source_ranges);
+        let traffic_policy = TrafficPolicy::parse(spec.external_traffic_policy.as_deref());
+        let health_check_node_port = spec.health_check_node_port.and_then(|port| u16::try_from(port).ok()).filter(|port| *port != 0);
 
-    pub async fn watch_services(&self) -> Result<()> {
-        let svc_api: Api<Service> = Api::all(self.client.clone());
-        let params = ListParams::default();
+        Ok(BalancedService::new(spec.selector, Self::parse_port_map(spec.ports), source_ranges, traffic_policy, health_check_node_port))
+    }
 
-        let watcher = watcher(svc_api, params);
-        watcher.try_for_each(|ev| async {
+    #[instrument(skip(self))]
+    pub async fn watch_services(self: &Arc<Self>) -> Result<()> {
+        self.source.watch_services().try_for_each(|ev| async {
             match ev {
                 // Update or delete
                 Event::Applied(svc) => {
-                    let name = match svc.metadata.name.clone() {
-                        Some(v) => v,
-                        None => return Ok(()),
-                    };
-
-                    let namespace = match svc.metadata.namespace.clone() {
-                        Some(v) => v,
-                        None => return Ok(()),
-                    };
-
-                    if name == self.config.service_name && namespace == self.config.service_namespace {
-                        match Self::map_service(svc) {
-                            Ok(svc) => {
-                                info!("Service {} registered with port map {:?}", self.config.service_name, svc.port_map);
-                                *self.service.write() = Some(svc);
-
-                                // Reload pods
-                                if let Err(e) = self.seed_pods().await {
-                                    error!("Error while re-seeding pods: {}", e);
-                                }
-                            }
-                            Err(e) => {
-                                error!("Error while registering service: {}", e);
-                                *self.service.write() = None;
-                            }
-                        }
+                    if self.is_balanced(&svc) {
+                        self.apply_service(svc);
                     }
                 }
 
                 Event::Deleted(svc) => {
-                    if let Some(name) = svc.metadata.name {
-                        if let Some(namespace) = svc.metadata.namespace {
-                            if name == self.config.service_name && namespace == self.config.service_namespace {
-                                *self.service.write() = None
-                            }
-                        }
+                    if self.is_balanced(&svc) {
+                        self.set_service(None);
                     }
                 }
 
-                Event::Restarted(_services) => {
+                // The service may have changed or gone while the watch was down
+                Event::Restarted(services) => {
                     info!("Got service stream restarted");
-                    // TODO
+
+                    match services.into_iter().find(|svc| self.is_balanced(svc)) {
+                        Some(svc) => self.apply_service(svc),
+                        None => {
+                            if self.has_service() {
+                                warn!("Service {} is gone", self.config().service);
+                            }
+
+                            self.set_service(None);
+                        }
+                    }
                 }
             }
 
             Ok(())
-        }).await.map_err(NodeBalancerError::WatcherError)?;
+        }).await
+    }
 
-        Ok(())
+    // Whether `svc` is the configured service
+    fn is_balanced(&self, svc: &Service) -> bool {
+        let config = self.config();
+        svc.metadata.name.as_deref() == Some(&config.service.name) && svc.metadata.namespace.as_deref() == Some(&config.service.namespace)
+    }
+
+    fn apply_service(self: &Arc<Self>, svc: Service) {
+        let ingress = svc.status.as_ref()
+            .and_then(|status| status.load_balancer.as_ref())
+            .map(|load_balancer| load_balancer.ingress.clone())
+            .unwrap_or_default();
+        self.set_service_ingress(ingress);
+
+        match self.map_service(svc) {
+            Ok(svc) => {
+                info!("Service {} registered with externalTrafficPolicy {} and port map {:?}", self.config().service, svc.traffic_policy, svc.port_map);
+                self.set_service(Some(svc));
+
+                // Reload pods
+                self.reseed_pods();
+            }
+            Err(e) => {
+                error!("Error while registering service: {}", e);
+                self.set_service(None);
+            }
+        }
+    }
+
+    fn parse_source_ranges(ranges: Vec<String>) -> Vec<IpNet> {
+        ranges.into_iter()
+            .filter_map(|range| match range.trim().parse() {
+                Ok(net) => Some(net),
+                Err(_) => {
+                    warn!("Ignoring invalid loadBalancerSourceRanges entry {:?}", range);
+                    None
+                }
+            })
+            .collect()
     }
 
     fn parse_port_map(ports: Vec<ServicePort>) -> PortMap {
-        ports.iter()
-            .filter_map(|port| port.node_port.map(|node_port| (port.port as u16, node_port as u16)))
+        ports.into_iter()
+            .filter_map(|port| {
+                let node_port = port.node_port?;
+
+                match Protocol::parse(port.protocol.as_deref()) {
+                    Some(protocol) => Some(MappedPort::new(port.name, protocol, port.port as u16, node_port as u16)),
+                    None => {
+                        warn!("Ignoring service port {} with unknown protocol {:?}", port.port, port.protocol);
+                        None
+                    }
+                }
+            })
             .collect()
     }
 }
\ No newline at end of file
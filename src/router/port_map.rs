@@ -1,3 +1,97 @@
-use std::collections::HashMap;
+use crate::router::{MappedPort, Protocol};
+use crate::config::PortRef;
+use std::collections::BTreeMap;
+use std::iter::FromIterator;
 
-pub type PortMap = HashMap<u16, u16>;
\ No newline at end of file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PortMap {
+    // (protocol, service port) -> port
+    ports: BTreeMap<(Protocol, u16), MappedPort>,
+}
+
+impl PortMap {
+    pub fn new() -> PortMap {
+        PortMap::default()
+    }
+
+    pub fn insert(&mut self, port: MappedPort) {
+        self.ports.insert((port.protocol, port.port), port);
+    }
+
+    pub fn get(&self, protocol: Protocol, port: u16) -> Option<&MappedPort> {
+        self.ports.get(&(protocol, port))
+    }
+
+    pub fn get_by_name(&self, protocol: Protocol, name: &str) -> Option<&MappedPort> {
+        self.ports(protocol).find(|port| port.name.as_deref() == Some(name))
+    }
+
+    pub fn resolve(&self, protocol: Protocol, port: &PortRef) -> Option<&MappedPort> {
+        match port {
+            PortRef::Number(number) => self.get(protocol, *number),
+            PortRef::Name(name) => self.get_by_name(protocol, name),
+        }
+    }
+
+    /// Ports of a single protocol, in service port order
+    pub fn ports(&self, protocol: Protocol) -> impl Iterator<Item = &MappedPort> {
+        self.ports.range((protocol, 0)..=(protocol, u16::MAX)).map(|(_, port)| port)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MappedPort> {
+        self.ports.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ports.is_empty()
+    }
+}
+
+impl FromIterator<MappedPort> for PortMap {
+    fn from_iter<I: IntoIterator<Item = MappedPort>>(iter: I) -> PortMap {
+        let mut port_map = PortMap::new();
+        iter.into_iter().for_each(|port| port_map.insert(port));
+        port_map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port_map() -> PortMap {
+        vec![
+            MappedPort::new(Some("https".to_owned()), Protocol::Tcp, 443, 30443),
+            MappedPort::new(Some("http".to_owned()), Protocol::Tcp, 80, 30080),
+            MappedPort::new(Some("dns".to_owned()), Protocol::Udp, 53, 30053),
+            MappedPort::new(None, Protocol::Tcp, 53, 31053),
+        ].into_iter().collect()
+    }
+
+    #[test]
+    fn resolves_by_number_and_name_within_a_protocol() {
+        let port_map = port_map();
+
+        assert_eq!(port_map.resolve(Protocol::Tcp, &PortRef::Number(80)).map(|port| port.node_port), Some(30080));
+        assert_eq!(port_map.resolve(Protocol::Tcp, &PortRef::Name("https".to_owned())).map(|port| port.node_port), Some(30443));
+        assert_eq!(port_map.resolve(Protocol::Tcp, &PortRef::Name("dns".to_owned())), None);
+        assert_eq!(port_map.resolve(Protocol::Udp, &PortRef::Number(53)).map(|port| port.node_port), Some(30053));
+        assert_eq!(port_map.resolve(Protocol::Tcp, &PortRef::Number(53)).map(|port| port.node_port), Some(31053));
+    }
+
+    #[test]
+    fn lists_one_protocol_in_port_order() {
+        let ports: Vec<u16> = port_map().ports(Protocol::Tcp).map(|port| port.port).collect();
+        assert_eq!(ports, vec![53, 80, 443]);
+    }
+
+    #[test]
+    fn compares_by_contents() {
+        let mut changed = port_map();
+        assert_eq!(changed, port_map());
+
+        changed.insert(MappedPort::new(Some("http".to_owned()), Protocol::Tcp, 80, 30081));
+        assert_ne!(changed, port_map());
+        assert!(PortMap::new().is_empty());
+    }
+}
@@ -1,55 +1,236 @@
-use kube::{Client, Config as KubeConfig};
-use std::convert::TryFrom;
 use crate::{Result, NodeBalancerError, Config};
-use crate::router::{AddressableNode, BalancedService, BackendPod};
-use parking_lot::RwLock;
+use crate::config::{PortRef, Strategy};
+use crate::metrics::metrics;
+use crate::router::{AddressableNode, BalancedService, BackendPod, Destination, NodeHealth, NodeAddress, DnsCache, PortMap, Protocol, TrafficPolicy};
+use parking_lot::{Mutex, RwLock};
 use rand::seq::SliceRandom;
-use std::collections::{HashMap, BTreeMap};
-use log::{error, info};
+use std::collections::{HashMap, HashSet, BTreeMap};
+use tracing::{error, info, info_span, Instrument};
 use std::sync::Arc;
+use std::net::IpAddr;
+use ipnet::IpNet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::future::Future;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use crate::backoff::{Backoff, retry};
+use crate::connections::{Backend, ConnectionTracker};
+use crate::events::EventRecorder;
+use crate::cluster_source::ClusterSource;
+use k8s_openapi::api::core::v1::LoadBalancerIngress;
 
 pub struct Router {
-    pub config: Arc<Config>,
-    pub(super) client: Client,
+    pub config: watch::Receiver<Arc<Config>>,
+    pub connections: Arc<ConnectionTracker>,
+    pub events: Arc<EventRecorder>,
+    // Where nodes, services and pods are listed and watched from
+    pub(super) source: Arc<dyn ClusterSource>,
     // name -> node
     pub(super) nodes: RwLock<HashMap<String, AddressableNode>>,
     // name -> svc
     pub(super) service: RwLock<Option<BalancedService>>,
+    // Published whenever the service's port map changes, empty while there is no service
+    port_map_tx: watch::Sender<PortMap>,
+    // The service's status.loadBalancer.ingress as the watcher last saw it
+    service_ingress_tx: watch::Sender<Vec<LoadBalancerIngress>>,
     // name -> pod
     pub(super) pods: RwLock<HashMap<String, BackendPod>>,
     pub(super) pod_names: RwLock<Vec<String>>,
+    // node name -> health, nodes without an entry are assumed healthy
+    pub(super) node_health: RwLock<HashMap<String, NodeHealth>>,
+    round_robin: AtomicUsize,
+    dns_cache: DnsCache,
+    // The node this replica runs on, for finding its zone
+    node_name: Option<String>,
+    // The pod re-list started by the last service change, replaced by the next one
+    pod_reseed: Mutex<Option<JoinHandle<()>>>,
+    // Set to stop the watchers and other background tasks
+    stop_tx: watch::Sender<bool>,
 }
 
 impl Router {
-    pub async fn new(config: Arc<Config>) -> Router {
-        let kube_config = KubeConfig::infer().await.unwrap();
-        let client = Client::try_from(kube_config).unwrap();
-
+    /// `node_name` is the node this replica runs on, if known, whose zone is the local one for topology
+    pub fn new(config: watch::Receiver<Arc<Config>>, source: Arc<dyn ClusterSource>, node_name: Option<String>) -> Router {
         Router {
+            events: Arc::new(EventRecorder::new(source.kube_client(), config.clone())),
             config,
-            client,
+            connections: Arc::new(ConnectionTracker::new()),
+            source,
             nodes: RwLock::new(HashMap::new()),
             service: RwLock::new(None),
+            port_map_tx: watch::channel(PortMap::new()).0,
+            service_ingress_tx: watch::channel(Vec::new()).0,
             pods: RwLock::new(HashMap::new()),
             pod_names: RwLock::new(Vec::new()),
+            node_health: RwLock::new(HashMap::new()),
+            round_robin: AtomicUsize::new(0),
+            dns_cache: DnsCache::new(),
+            node_name,
+            pod_reseed: Mutex::new(None),
+            stop_tx: watch::channel(false).0,
+        }
+    }
+
+    pub fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.borrow())
+    }
+
+    pub fn watch_port_map(&self) -> watch::Receiver<PortMap> {
+        self.port_map_tx.subscribe()
+    }
+
+    /// Follows the service's load balancer ingress as seen by the service watcher
+    pub fn watch_service_ingress(&self) -> watch::Receiver<Vec<LoadBalancerIngress>> {
+        self.service_ingress_tx.subscribe()
+    }
+
+    pub fn has_service(&self) -> bool {
+        self.service.read().is_some()
+    }
+
+    /// Routable pods and how many nodes they're spread over
+    pub fn backend_counts(&self) -> (usize, usize) {
+        let pod_names = self.pod_names.read();
+        let pods = self.pods.read();
+        let nodes = self.nodes.read();
+
+        let backend_nodes: HashSet<&String> = pod_names.iter()
+            .filter_map(|pod_name| pods.get(pod_name))
+            .map(|pod| &pod.node)
+            .filter(|node| nodes.contains_key(*node))
+            .collect();
+
+        (pod_names.len(), backend_nodes.len())
+    }
+
+    /// The service's loadBalancerSourceRanges, empty while there is no service
+    pub fn service_source_ranges(&self) -> Vec<IpNet> {
+        self.service.read().as_ref().map(|svc| svc.source_ranges.clone()).unwrap_or_default()
+    }
+
+    pub(super) fn set_service(&self, service: Option<BalancedService>) {
+        let port_map = service.as_ref().map(|svc| svc.port_map.clone()).unwrap_or_default();
+        *self.service.write() = service;
+
+        if *self.port_map_tx.borrow() != port_map {
+            self.port_map_tx.send_replace(port_map);
+        }
+    }
+
+    pub(super) fn set_service_ingress(&self, ingress: Vec<LoadBalancerIngress>) {
+        if *self.service_ingress_tx.borrow() != ingress {
+            self.service_ingress_tx.send_replace(ingress);
         }
     }
 
     // TODO: Filter services by annotation name
-    pub fn get_destination(&self, port: u16) -> Result<(String, u16)> {
+    pub fn get_destination(&self, port: &PortRef, strategy: &Strategy) -> Result<Destination> {
+        let destination = self.pick_destination(port, strategy);
+        if let Err(e) = &destination {
+            self.report(e, port);
+        }
+
+        destination
+    }
+
+    // Raises an event for failures someone looking at the cluster can do something about
+    fn report(&self, error: &NodeBalancerError, port: &PortRef) {
+        let service = &self.config().service;
+        let found = self.has_service();
+
+        match error {
+            // Without the service there are no pods either, so that's the one worth reporting
+            NodeBalancerError::NoPodsAvailable | NodeBalancerError::ServiceNotFound if !found => {
+                self.events.service_warning(service, "ServiceNotFound", format!("service not found, can't route connections for port {}", port));
+            }
+            NodeBalancerError::NoPodsAvailable => {
+                self.events.service_warning(service, "NoRoutableBackends", format!("no routable backends for port {}", port));
+            }
+            NodeBalancerError::UnknownNode(node) => {
+                self.events.node_warning(node, "UnknownNode", format!("pods of service {} run on this node, but it isn't known to the balancer", service));
+            }
+            _ => {}
+        }
+    }
+
+    fn pick_destination(&self, port: &PortRef, strategy: &Strategy) -> Result<Destination> {
         // Race condition shouldn't occur as we hold a read lock on pod_names until the end
         // (enforced by the drop). Just make sure to get a write lock on pod_names before touching pods
         let pod_names = self.pod_names.read();
-        let pod_name = pod_names.choose(&mut rand::thread_rng()).ok_or(NodeBalancerError::NoPodsAvailable)?;
-
         let pods = self.pods.read();
-        let pod = pods.get(pod_name).ok_or(NodeBalancerError::NoPodsAvailable)?;
-        drop(pod_names);
 
-        // Get node IP
+        // Draining nodes have already been removed from nodes
+        let node_health = self.node_health.read();
         let nodes = self.nodes.read();
-        let node_ips = &nodes.get(&pod.node).ok_or_else(|| NodeBalancerError::UnknownNode(pod.node.clone()))?.addresses;
-        let ip = node_ips.choose(&mut rand::thread_rng()).ok_or_else(|| NodeBalancerError::NoAddressesAvailable(pod.node.clone()))?;
+        let healthy = |node: &String| nodes.contains_key(node) && node_health.get(node).is_none_or(|health| health.healthy);
+
+        // pod_names only holds ready pods that aren't terminating, so with the Local policy every candidate node
+        // has an endpoint kube-proxy will forward to
+        let candidates: Vec<&String> = pod_names.iter()
+            .filter(|pod_name| pods.get(*pod_name).is_some_and(|pod| healthy(&pod.node)))
+            .collect();
+
+        let config = self.config();
+        let zone = self.local_zone(&config, &nodes);
+        let (candidates, narrowed) = match &zone {
+            Some(zone) => Self::prefer_zone(candidates, zone, config.topology.min_local_share, &pods, &nodes),
+            None => (candidates, false),
+        };
+
+        // node -> its candidate pods, ordered so round robin is stable
+        let mut by_node: BTreeMap<&String, Vec<&String>> = BTreeMap::new();
+        for pod_name in candidates {
+            if let Some(pod) = pods.get(pod_name) {
+                by_node.entry(&pod.node).or_default().push(pod_name);
+            }
+        }
+
+        // With the Cluster policy nodes without pods can still forward through kube-proxy, but they only get
+        // connections with a weight. With Local they would drop them.
+        let traffic_policy = self.service.read().as_ref().map_or(TrafficPolicy::Cluster, |svc| svc.traffic_policy);
+        if config.node_weights.empty_node_weight > 0 && traffic_policy == TrafficPolicy::Cluster {
+            nodes.iter()
+                .filter(|(name, node)| healthy(name) && (!narrowed || node.zone == zone))
+                .for_each(|(name, _)| {
+                    by_node.entry(name).or_default();
+                });
+        }
+        drop(node_health);
+
+        let weighted: Vec<(&String, Vec<&String>, u32)> = by_node.into_iter()
+            .map(|(node, node_pods)| {
+                let weight = nodes.get(node).map_or(0, |node| node.weight(node_pods.len(), &config.node_weights));
+                (node, node_pods, weight)
+            })
+            .filter(|(_, _, weight)| *weight > 0)
+            .collect();
+
+        let (node_name, pod_name) = match strategy {
+            // Unknown strategies never make it past validation
+            Strategy::Random | Strategy::Unknown(_) => {
+                let mut rng = rand::thread_rng();
+                let (node_name, node_pods, _) = weighted.choose_weighted(&mut rng, |(_, _, weight)| *weight)
+                    .map_err(|_| NodeBalancerError::NoPodsAvailable)?;
+                (*node_name, node_pods.choose(&mut rng))
+            }
+            Strategy::RoundRobin => {
+                let turn = self.round_robin.fetch_add(1, Ordering::Relaxed);
+                let (node_name, node_pods, _) = Self::nth_weighted(&weighted, turn).ok_or(NodeBalancerError::NoPodsAvailable)?;
+                (*node_name, node_pods.get(turn % node_pods.len().max(1)))
+            }
+        };
+
+        let node_name = node_name.clone();
+        let pod_name = pod_name.map(|pod_name| (*pod_name).clone());
+        drop(pod_names);
+
+        // Get node address
+        let node = nodes.get(&node_name).ok_or_else(|| NodeBalancerError::UnknownNode(node_name.clone()))?;
+        let node_addresses = node.select_addresses(&config.node_addresses);
+
+        if node_addresses.is_empty() {
+            return NodeBalancerError::NoAddressesAvailable(node_name).into();
+        }
 
         // Get service port
         // Rust is dumb
@@ -57,70 +238,223 @@ impl Router {
         let dest_port = lock.as_ref()
             .ok_or(NodeBalancerError::ServiceNotFound)?
             .port_map
-            .get(&port)
-            .ok_or_else(|| NodeBalancerError::UnknownPort(port))?;
+            .resolve(Protocol::Tcp, port)
+            .ok_or_else(|| NodeBalancerError::UnknownPort(port.clone()))?
+            .node_port;
+
+        if zone.is_some() && node.zone != zone {
+            metrics().cross_zone_connections.inc();
+        }
+
+        Ok(Destination::new(pod_name, node_name, node_addresses, dest_port))
+    }
+
+    // Goes through the nodes in order, each taking as many turns as its weight
+    fn nth_weighted<T>(weighted: &[(T, Vec<T>, u32)], turn: usize) -> Option<&(T, Vec<T>, u32)> {
+        let total: u64 = weighted.iter().map(|(_, _, weight)| u64::from(*weight)).sum();
+        if total == 0 {
+            return None;
+        }
 
-        Ok((ip.clone(), *dest_port))
+        let mut remaining = turn as u64 % total;
+        weighted.iter().find(|(_, _, weight)| {
+            if remaining < u64::from(*weight) {
+                return true;
+            }
+
+            remaining -= u64::from(*weight);
+            false
+        })
     }
 
+    // The balancer's zone while topology is enabled, if it's configured or its node has one
+    fn local_zone(&self, config: &Config, nodes: &HashMap<String, AddressableNode>) -> Option<String> {
+        if !config.topology.enabled {
+            return None;
+        }
+
+        config.topology.zone.clone()
+            .or_else(|| nodes.get(self.node_name.as_ref()?)?.zone.clone())
+    }
+
+    // Keeps only the candidates in `zone` if they make up at least `min_share` of them, like topology aware
+    // hints do for services, otherwise spills over to every zone. Also returns whether it narrowed them.
+    fn prefer_zone<'a>(candidates: Vec<&'a String>, zone: &str, min_share: f64, pods: &HashMap<String, BackendPod>, nodes: &HashMap<String, AddressableNode>) -> (Vec<&'a String>, bool) {
+        let local: Vec<&String> = candidates.iter()
+            .filter(|pod_name| {
+                pods.get(**pod_name)
+                    .and_then(|pod| nodes.get(&pod.node))
+                    .is_some_and(|node| node.zone.as_deref() == Some(zone))
+            })
+            .copied()
+            .collect();
+
+        if local.is_empty() || (local.len() as f64) < candidates.len() as f64 * min_share {
+            return (candidates, false);
+        }
+
+        (local, true)
+    }
+
+    pub async fn resolve(&self, address: &NodeAddress) -> Result<Vec<IpAddr>> {
+        match address {
+            NodeAddress::Ip(ip) => Ok(vec![*ip]),
+            NodeAddress::Hostname(hostname) => self.dns_cache.resolve(hostname, self.config().node_addresses.dns_ttl).await,
+        }
+    }
+
+    /// Lists nodes, the service and its pods. Retries until each succeeds, unless the source says retrying
+    /// won't help, in which case the first failure is returned.
     pub async fn seed(&self) -> Result<()> {
-        self.seed_nodes().await?;
-        self.seed_service().await?;
-        self.seed_pods().await?;
+        self.seed_phase("nodes", || self.seed_nodes()).await?;
+        self.seed_phase("service", || self.seed_service()).await?;
+        self.seed_phase("pods", || self.seed_pods()).await
+    }
+
+    async fn seed_phase<F, Fut>(&self, phase: &'static str, mut seed: F) -> Result<()>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let span = info_span!("seed", phase);
+
+        if !self.source.retries_lists() {
+            return seed().instrument(span).await;
+        }
 
+        let backoff = self.config().backoff.clone();
+        retry(&backoff, &format!("Seeding {}", phase), seed).instrument(span).await;
         Ok(())
     }
 
+    // Re-lists pods in the background so the service watcher keeps going while the list is failing. A re-list
+    // still retrying is replaced, the newer service is the one whose pods matter.
+    pub(super) fn reseed_pods(self: &Arc<Self>) {
+        let router = Arc::clone(self);
+        let task = self.spawn_task(async move {
+            let backoff = router.config().backoff.clone();
+            retry(&backoff, "Re-seeding pods", || router.seed_pods()).instrument(info_span!("seed", phase = "pods")).await;
+        });
+
+        if let Some(previous) = self.pod_reseed.lock().replace(task) {
+            previous.abort();
+        }
+    }
+
     pub async fn start_watchers(self: Arc<Self>, daemon: bool) {
-        let node_handle = tokio::spawn({
+        let node_handle = self.spawn_watcher("node", |router| async move { router.watch_nodes().await });
+        let svc_handle = self.spawn_watcher("service", |router| async move { router.watch_services().await });
+        let pod_handle = self.spawn_watcher("pod", |router| async move { router.watch_pods().await });
+
+        let config_handle = self.spawn_task({
             let router = Arc::clone(&self);
+            async move { router.watch_config().await }
+        });
 
-            async move {
-                loop {
-                    if let Err(e) = router.clone().watch_nodes().await {
-                        error!("Error returned by node watcher: {}", e);
-                    }
+        let health_handle = self.spawn_task({
+            let router = Arc::clone(&self);
+            async move { router.run_health_checks().await }
+        });
+
+        #[allow(unused_must_use)]
+        if !daemon {
+            // Only returns once stop_watchers is called
+            tokio::join!(node_handle, svc_handle, pod_handle, config_handle, health_handle);
+        }
+    }
+
+    pub fn stop_watchers(&self) {
+        self.stop_tx.send_replace(true);
+    }
+
+    // Spawns a background task that runs until stop_watchers is called
+    fn spawn_task<F>(&self, task: F) -> JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut stop = self.stop_tx.subscribe();
 
-                    info!("Restarting node watcher");
+        tokio::spawn(async move {
+            let stopped = async {
+                while !*stop.borrow() {
+                    if stop.changed().await.is_err() {
+                        return;
+                    }
                 }
+            };
+
+            tokio::select! {
+                _ = task => {}
+                _ = stopped => {}
             }
-        });
+        })
+    }
 
-        let svc_handle = tokio::spawn({
-            let router = Arc::clone(&self);
+    fn spawn_watcher<F, Fut>(self: &Arc<Self>, kind: &'static str, watch: F) -> JoinHandle<()>
+    where
+        F: Fn(Arc<Router>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let router = Arc::clone(self);
 
-            async move {
-                loop {
-                    if let Err(e) = router.clone().watch_services().await {
-                        error!("Error returned by service watcher: {}", e);
-                    }
+        self.spawn_task(async move {
+            let mut backoff = Backoff::new(router.config().backoff.clone());
+
+            loop {
+                backoff.start();
 
-                    info!("Restarting service watcher");
+                if let Err(e) = watch(Arc::clone(&router)).await {
+                    error!("Error returned by {} watcher: {}", kind, e);
                 }
+
+                let delay = backoff.next_delay();
+                info!("Restarting {} watcher in {:?}", kind, delay);
+                tokio::time::sleep(delay).await;
             }
-        });
+        })
+    }
 
-        let pod_handle = tokio::spawn({
-            let router = Arc::clone(&self);
+    // Re-seeds the service and its pods when the config points the balancer at a different service
+    async fn watch_config(&self) {
+        let mut config = self.config.clone();
+        let mut seeded = Some(config.borrow().service.clone());
 
-            async move {
-                loop {
-                    if let Err(e) = router.clone().watch_pods().await {
-                        error!("Error returned by pod watcher: {}", e);
-                    }
+        loop {
+            let wanted = config.borrow().service.clone();
+
+            if seeded.as_ref() != Some(&wanted) {
+                info!("Balanced service changed to {}, re-seeding", wanted);
+
+                // Marked unseeded first, so an interrupted re-seed is always retried
+                seeded = None;
+                self.set_service(None);
 
-                    info!("Restarting pod watcher");
+                let backoff = self.config().backoff.clone();
+                let reseed = async {
+                    retry(&backoff, "Seeding service", || self.seed_service()).instrument(info_span!("seed", phase = "service")).await;
+                    retry(&backoff, "Seeding pods", || self.seed_pods()).instrument(info_span!("seed", phase = "pods")).await;
+                };
+
+                tokio::select! {
+                    _ = reseed => seeded = Some(wanted),
+                    res = config.changed() => if res.is_err() {
+                        return;
+                    },
                 }
+
+                continue;
             }
-        });
 
-        #[allow(unused_must_use)]
-        if !daemon {
-            // Will never return
-            tokio::join!(node_handle, svc_handle, pod_handle);
+            if config.changed().await.is_err() {
+                return;
+            }
         }
     }
 
+    pub(super) fn drain(&self, backend: Backend) {
+        self.connections.drain(backend, self.config().drain.timeout);
+    }
+
     async fn seed_nodes(&self) -> Result<()> {
         let nodes = self.fetch_nodes().await?;
         *self.nodes.write() = nodes;
@@ -128,32 +462,340 @@ impl Router {
     }
 
     async fn seed_service(&self) -> Result<()> {
-        let service = self.fetch_service().await?;
-        *self.service.write() = Some(service);
+        let service = match self.fetch_service().await {
+            Ok(service) => service,
+            Err(NodeBalancerError::ServiceNotFound) => {
+                self.events.service_warning(&self.config().service, "ServiceNotFound", "service not found, can't route connections".to_owned());
+                return NodeBalancerError::ServiceNotFound.into();
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.set_service(Some(service));
         Ok(())
     }
 
     pub(super) async fn seed_pods(&self) -> Result<()> {
-        // Rust compiler is dumb
-        let selector: BTreeMap<String, String>;
-        {
-            selector = self.service.read().clone().expect("cumzone").selector;
-        }
+        let selector: BTreeMap<String, String> = self.service.read().as_ref()
+            .ok_or(NodeBalancerError::ServiceNotFound)?
+            .selector
+            .clone();
 
-        // Ok to panic, only run on startup and after successful service replacement
         let fetched_pods = self.fetch_pods(&selector).await?;
+        self.replace_pods(fetched_pods);
 
-        let pod_names = &mut self.pod_names.write();
-        let pods = &mut self.pods.write();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster_source::MemorySource;
+    use hyper::{Body, Method, Request, Response, Server, StatusCode};
+    use hyper::service::{make_service_fn, service_fn};
+    use std::convert::{Infallible, TryFrom};
+    use std::time::Duration;
+    use k8s_openapi::api::core::v1::{Node, Pod, Service};
+    use serde_json::json;
+
+    fn node(name: &str) -> Node {
+        serde_json::from_value(json!({
+            "metadata": { "name": name },
+            "status": { "addresses": [{ "type": "InternalIP", "address": "10.0.0.1" }] },
+        })).unwrap()
+    }
 
-        pods.clear();
-        pod_names.clear();
+    fn pod(name: &str, node: &str, ready: bool) -> Pod {
+        serde_json::from_value(json!({
+            "metadata": { "namespace": "default", "name": name, "labels": { "app": "web" } },
+            "spec": { "nodeName": node, "containers": [] },
+            "status": { "conditions": [{ "type": "Ready", "status": if ready { "True" } else { "False" } }] },
+        })).unwrap()
+    }
 
-        fetched_pods.into_iter().for_each(|(pod_name, pod)| {
-            pods.insert(pod_name.clone(), pod);
-            pod_names.push(pod_name);
+    fn service(traffic_policy: &str) -> Service {
+        serde_json::from_value(json!({
+            "metadata": { "namespace": "default", "name": "web" },
+            "spec": {
+                "type": "NodePort",
+                "selector": { "app": "web" },
+                "ports": [{ "port": 80, "nodePort": 30080, "protocol": "TCP" }],
+                "externalTrafficPolicy": traffic_policy,
+            },
+        })).unwrap()
+    }
+
+    fn node_in(name: &str, zone: &str) -> Node {
+        let mut node = node(name);
+        node.metadata.labels.insert("topology.kubernetes.io/zone".to_owned(), zone.to_owned());
+        node
+    }
+
+    fn weighted_node(name: &str, weight: &str) -> Node {
+        let mut node = node(name);
+        node.metadata.annotations.insert("node-balancer.io/weight".to_owned(), weight.to_owned());
+        node
+    }
+
+    async fn seeded(config: Config, source: &Arc<MemorySource>) -> Router {
+        seeded_on(config, source, None).await
+    }
+
+    async fn seeded_on(mut config: Config, source: &Arc<MemorySource>, node_name: Option<&str>) -> Router {
+        config.service.name = "web".to_owned();
+
+        let node_name = node_name.map(str::to_owned);
+        let router = Router::new(watch::channel(Arc::new(config)).1, Arc::clone(source) as Arc<dyn ClusterSource>, node_name);
+        router.seed().await.unwrap();
+        router
+    }
+
+    // The nodes picked for `count` connections in a row
+    fn destinations(router: &Router, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|_| router.get_destination(&PortRef::Number(80), &Strategy::RoundRobin).unwrap().node)
+            .collect()
+    }
+
+    fn weighted(weights: &[u32]) -> Vec<(usize, Vec<usize>, u32)> {
+        weights.iter().enumerate().map(|(node, weight)| (node, Vec::new(), *weight)).collect()
+    }
+
+    fn turns(weights: &[u32], count: usize) -> Vec<usize> {
+        let weighted = weighted(weights);
+        (0..count).map(|turn| Router::nth_weighted(&weighted, turn).unwrap().0).collect()
+    }
+
+    #[test]
+    fn round_robin_gives_each_node_its_weight_in_turns() {
+        assert_eq!(turns(&[1, 1, 1], 6), vec![0, 1, 2, 0, 1, 2]);
+        assert_eq!(turns(&[3, 1], 8), vec![0, 0, 0, 1, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn zero_weights_are_skipped() {
+        assert_eq!(turns(&[0, 2, 0, 1], 6), vec![1, 1, 3, 1, 1, 3]);
+        assert!(Router::nth_weighted(&weighted(&[0, 0]), 0).is_none());
+        assert!(Router::nth_weighted(&weighted(&[]), 0).is_none());
+    }
+
+    #[test]
+    fn large_weights_and_turns_do_not_overflow() {
+        let weighted = weighted(&[u32::MAX, u32::MAX, 1]);
+
+        assert_eq!(Router::nth_weighted(&weighted, usize::MAX).map(|(node, _, _)| *node), Some(0));
+        assert_eq!(Router::nth_weighted(&weighted, u32::MAX as usize * 2).map(|(node, _, _)| *node), Some(2));
+    }
+
+    #[tokio::test]
+    async fn local_policy_only_routes_to_nodes_with_ready_pods() {
+        let source = Arc::new(MemorySource::new());
+        source.apply_service(service("Local"));
+        source.apply_node(node("ready"));
+        source.apply_node(node("unready"));
+        source.apply_pod(pod("web-1", "ready", true));
+        source.apply_pod(pod("web-2", "unready", false));
+
+        // Even with nodes without pods weighted in, which Local leaves out
+        let mut config = Config::default();
+        config.node_weights.empty_node_weight = 1;
+        let router = seeded(config, &source).await;
+
+        assert_eq!(destinations(&router, 4), vec!["ready"; 4]);
+    }
+
+    // An API server that creates whatever events it's sent, returning how many, and knows of nothing else
+    fn event_sink() -> (kube::Client, Arc<AtomicUsize>) {
+        let created = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&created);
+
+        let make_service = make_service_fn(move |_| {
+            let created = Arc::clone(&counter);
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let created = Arc::clone(&created);
+
+                    async move {
+                        if req.method() != Method::POST || !req.uri().path().ends_with("/events") {
+                            return Ok::<_, Infallible>(Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()).unwrap());
+                        }
+
+                        created.fetch_add(1, Ordering::Relaxed);
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap_or_default();
+                        Ok(Response::builder().status(StatusCode::CREATED).body(Body::from(body)).unwrap())
+                    }
+                }))
+            }
         });
 
-        Ok(())
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let url = format!("http://{}", server.local_addr()).parse().unwrap();
+        tokio::spawn(server);
+
+        (kube::Client::try_from(kube::Config::new(url)).unwrap(), created)
+    }
+
+    #[tokio::test]
+    async fn pods_in_other_namespaces_are_ignored() {
+        let source = Arc::new(MemorySource::new());
+        source.apply_service(service("Local"));
+        source.apply_node(node("n1"));
+        source.apply_node(node("n2"));
+        source.apply_pod(pod("web-1", "n1", true));
+
+        let mut elsewhere = pod("web-2", "n2", true);
+        elsewhere.metadata.namespace = Some("other".to_owned());
+        source.apply_pod(elsewhere);
+
+        let router = Arc::new(seeded(Config::default(), &source).await);
+        assert_eq!(destinations(&router, 2), vec!["n1"; 2]);
+
+        // Nor does one sharing a name with a backend take its place
+        Arc::clone(&router).start_watchers(true).await;
+        let mut namesake = pod("web-1", "n2", true);
+        namesake.metadata.namespace = Some("other".to_owned());
+        source.apply_pod(namesake);
+        source.delete_pod("other", "web-1");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(destinations(&router, 2), vec!["n1"; 2]);
+        router.stop_watchers();
+    }
+
+    #[tokio::test]
+    async fn seeding_an_unsupported_service_raises_an_event() {
+        let (client, created) = event_sink();
+        let source = Arc::new(MemorySource::new().with_kube_client(client));
+        let mut cluster_ip = service("Cluster");
+        cluster_ip.spec.as_mut().unwrap().type_ = Some("ClusterIP".to_owned());
+        source.apply_service(cluster_ip);
+
+        let mut config = Config::default();
+        config.service.name = "web".to_owned();
+        config.events.enabled = true;
+        let router = Router::new(watch::channel(Arc::new(config)).1, source, None);
+        router.events.set_leading(true);
+
+        assert!(matches!(router.seed().await, Err(NodeBalancerError::WrongServiceType(_))));
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while created.load(Ordering::Relaxed) == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("no event was created");
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(created.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn seeding_fails_fast_without_the_service() {
+        let source = Arc::new(MemorySource::new());
+        source.apply_node(node("n1"));
+
+        let mut config = Config::default();
+        config.service.name = "web".to_owned();
+        let router = Router::new(watch::channel(Arc::new(config)).1, source, None);
+
+        assert!(matches!(router.seed().await, Err(NodeBalancerError::ServiceNotFound)));
+    }
+
+    #[tokio::test]
+    async fn prefers_nodes_in_the_balancers_zone() {
+        let source = Arc::new(MemorySource::new());
+        source.apply_service(service("Cluster"));
+        source.apply_node(node_in("a1", "a"));
+        source.apply_node(node_in("b1", "b"));
+        source.apply_pod(pod("web-1", "a1", true));
+        source.apply_pod(pod("web-2", "b1", true));
+
+        let mut config = Config::default();
+        config.topology.enabled = true;
+        config.topology.min_local_share = 0.1;
+
+        let router = seeded_on(config.clone(), &source, Some("b1")).await;
+        assert_eq!(destinations(&router, 4), vec!["b1"; 4]);
+
+        // Without knowing its node there's no local zone to prefer
+        let router = seeded_on(config, &source, None).await;
+        assert_eq!(destinations(&router, 4), vec!["a1", "b1", "a1", "b1"]);
+    }
+
+    #[tokio::test]
+    async fn deleting_a_nodes_last_pod_drains_it_under_local() {
+        let source = Arc::new(MemorySource::new());
+        source.apply_service(service("Local"));
+        source.apply_node(node("n1"));
+        source.apply_node(node("n2"));
+        source.apply_pod(pod("web-1", "n1", true));
+        source.apply_pod(pod("web-2", "n2", true));
+
+        let router = Arc::new(seeded(Config::default(), &source).await);
+        let _connection = router.connections.register("n2", 80);
+        Arc::clone(&router).start_watchers(true).await;
+
+        source.delete_pod("default", "web-2");
+
+        let n2 = Backend::Node("n2".to_owned());
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !router.connections.is_draining(&n2) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("n2 was not drained");
+
+        assert_eq!(destinations(&router, 3), vec!["n1"; 3]);
+        router.stop_watchers();
     }
-}
\ No newline at end of file
+
+    // Changes made before the watchers start stand in for ones missed while a watch was down
+    #[tokio::test]
+    async fn restarted_service_watch_applies_missed_changes() {
+        let source = Arc::new(MemorySource::new());
+        source.apply_service(service("Cluster"));
+        source.apply_node(node("n1"));
+        source.apply_pod(pod("web-1", "n1", true));
+
+        let router = Arc::new(seeded(Config::default(), &source).await);
+        let mut port_map = router.watch_port_map();
+
+        let mut changed = service("Cluster");
+        changed.spec.as_mut().unwrap().ports[0].node_port = Some(30081);
+        source.apply_service(changed);
+        Arc::clone(&router).start_watchers(true).await;
+
+        tokio::time::timeout(Duration::from_secs(5), port_map.changed()).await.unwrap().unwrap();
+        let destination = router.get_destination(&PortRef::Number(80), &Strategy::RoundRobin).unwrap();
+        assert_eq!(destination.port, 30081);
+        router.stop_watchers();
+
+        let router = Arc::new(seeded(Config::default(), &source).await);
+        source.delete_service("default", "web");
+        Arc::clone(&router).start_watchers(true).await;
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while router.has_service() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("the deleted service is still balanced");
+        router.stop_watchers();
+    }
+
+    #[tokio::test]
+    async fn nodes_are_weighted_by_annotation_or_pod_count() {
+        let source = Arc::new(MemorySource::new());
+        source.apply_service(service("Cluster"));
+        source.apply_node(weighted_node("n1", "3"));
+        source.apply_node(node("n2"));
+        source.apply_node(weighted_node("n3", "0"));
+        source.apply_pod(pod("web-1", "n1", true));
+        source.apply_pod(pod("web-2", "n2", true));
+        source.apply_pod(pod("web-3", "n2", true));
+        source.apply_pod(pod("web-4", "n3", true));
+
+        let router = seeded(Config::default(), &source).await;
+
+        assert_eq!(destinations(&router, 5), vec!["n1", "n1", "n1", "n2", "n2"]);
+    }
+}
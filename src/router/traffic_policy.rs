@@ -0,0 +1,29 @@
+use std::fmt;
+
+/// The service's externalTrafficPolicy, which decides whether a node can serve the NodePort without pods
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrafficPolicy {
+    // kube-proxy on any node forwards to pods anywhere in the cluster
+    Cluster,
+    // Only nodes hosting ready pods serve the NodePort, connections to the rest are dropped
+    Local,
+}
+
+impl TrafficPolicy {
+    // Kubernetes defaults externalTrafficPolicy to Cluster
+    pub fn parse(policy: Option<&str>) -> TrafficPolicy {
+        match policy {
+            Some("Local") => TrafficPolicy::Local,
+            _ => TrafficPolicy::Cluster,
+        }
+    }
+}
+
+impl fmt::Display for TrafficPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrafficPolicy::Cluster => write!(f, "Cluster"),
+            TrafficPolicy::Local => write!(f, "Local"),
+        }
+    }
+}
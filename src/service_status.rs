@@ -0,0 +1,196 @@
+use crate::{Result, NodeBalancerError};
+use crate::config::ServiceRef;
+use crate::leader_election::LeaderElector;
+use crate::router::Router;
+use k8s_openapi::api::core::v1::{LoadBalancerIngress, Service};
+use kube::{Api, Client};
+use kube::api::{Patch, PatchParams};
+use parking_lot::Mutex;
+use serde_json::json;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, info_span, warn, Instrument};
+
+// Re-checked this often in case something else changed the status
+const RESYNC_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Publishes the balancer's addresses in status.loadBalancer.ingress of the balanced service while this replica
+/// leads, so `kubectl get svc` and tools like external-dns see a real endpoint
+pub struct ServiceStatus {
+    router: Arc<Router>,
+    client: Client,
+    leader_election: Arc<LeaderElector>,
+    // The service whose status was last written, cleared once it is no longer balanced
+    written: tokio::sync::Mutex<Option<ServiceRef>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl ServiceStatus {
+    pub fn new(router: Arc<Router>, client: Client, leader_election: Arc<LeaderElector>) -> ServiceStatus {
+        ServiceStatus {
+            router,
+            client,
+            leader_election,
+            written: tokio::sync::Mutex::new(None),
+            task: Mutex::new(None),
+        }
+    }
+
+    pub fn start(self: &Arc<Self>) {
+        let status = Arc::clone(self);
+        *self.task.lock() = Some(tokio::spawn(async move { status.run().await }.instrument(info_span!("service_status"))));
+    }
+
+    /// Stops updating the status. Called before leadership is released, so the status is only cleared when this
+    /// is the sole replica: with leader election enabled another replica carries on serving the service.
+    pub async fn stop(&self) {
+        if let Some(task) = self.task.lock().take() {
+            task.abort();
+        }
+
+        if self.router.config().leader_election.enabled || !self.leader_election.is_leader() {
+            return;
+        }
+
+        if let Some(service) = self.written.lock().await.take() {
+            match self.write(&service, None).await {
+                Ok(true) => info!("Cleared the status of service {}", service),
+                Ok(false) => {}
+                Err(e) => warn!("Failed to clear the status of service {}: {}", service, e),
+            }
+        }
+    }
+
+    async fn run(&self) {
+        let mut config = self.router.config.clone();
+        let mut leading = self.leader_election.subscribe();
+        let mut ingress = self.router.watch_service_ingress();
+        let mut resync = tokio::time::interval(RESYNC_INTERVAL);
+
+        loop {
+            let is_leader = *leading.borrow();
+            self.reconcile(is_leader).await;
+
+            loop {
+                tokio::select! {
+                    res = config.changed() => if res.is_err() {
+                        return;
+                    } else {
+                        break;
+                    },
+                    res = leading.changed() => if res.is_err() {
+                        return;
+                    } else {
+                        break;
+                    },
+                    res = ingress.changed() => {
+                        if res.is_err() {
+                            return;
+                        }
+
+                        // Something else changed the status. Our own writes come back round here too, but match.
+                        if *leading.borrow() && self.differs(&ingress.borrow()) {
+                            break;
+                        }
+                    },
+                    _ = resync.tick() => break,
+                }
+            }
+        }
+    }
+
+    // Whether the service's status isn't what this replica would write
+    fn differs(&self, ingress: &[LoadBalancerIngress]) -> bool {
+        let config = self.router.config();
+        config.service_status.enabled && ingress != wanted_ingress(&config.service_status.ingress).as_slice()
+    }
+
+    async fn reconcile(&self, leading: bool) {
+        let mut written = self.written.lock().await;
+
+        // The new leader takes over, clearing it here would only make the service flap
+        if !leading {
+            *written = None;
+            return;
+        }
+
+        let config = self.router.config();
+        let wanted = Some(&config.service).filter(|_| config.service_status.enabled);
+
+        if let Some(previous) = written.as_ref().filter(|previous| Some(*previous) != wanted) {
+            match self.write(previous, None).await {
+                Ok(changed) => {
+                    if changed {
+                        info!("Cleared the status of service {}", previous);
+                    }
+
+                    *written = None;
+                }
+                Err(e) => warn!("Failed to clear the status of service {}: {}", previous, e),
+            }
+        }
+
+        if let Some(service) = wanted {
+            match self.write(service, Some(wanted_ingress(&config.service_status.ingress))).await {
+                Ok(changed) => {
+                    if changed {
+                        info!("Set the status of service {} to {}", service, config.service_status.ingress.join(", "));
+                    }
+
+                    *written = Some(service.clone());
+                }
+                Err(e) => warn!("Failed to write the status of service {}: {}", service, e),
+            }
+        }
+    }
+
+    // Sets or, with None, clears status.loadBalancer.ingress, returning whether it changed anything
+    async fn write(&self, service: &ServiceRef, ingress: Option<Vec<LoadBalancerIngress>>) -> Result<bool> {
+        let api: Api<Service> = Api::namespaced(self.client.clone(), &service.namespace);
+        let current = api.get_status(&service.name).await.map_err(NodeBalancerError::KubeError)?;
+
+        let service_type = current.spec.and_then(|spec| spec.type_).unwrap_or_default();
+        if service_type != "LoadBalancer" {
+            if ingress.is_some() {
+                warn!("Not writing the status of service {}, only LoadBalancer services have one, not {}", service, service_type);
+            }
+
+            return Ok(false);
+        }
+
+        let current = current.status
+            .and_then(|status| status.load_balancer)
+            .map(|load_balancer| load_balancer.ingress)
+            .unwrap_or_default();
+
+        if current == ingress.clone().unwrap_or_default() {
+            return Ok(false);
+        }
+
+        // A merge patch replaces the whole list, and null removes it
+        let patch = json!({ "status": { "loadBalancer": { "ingress": ingress } } });
+        api.patch_status(&service.name, &PatchParams::default(), &Patch::Merge(&patch)).await
+            .map_err(NodeBalancerError::KubeError)?;
+
+        Ok(true)
+    }
+}
+
+fn wanted_ingress(addresses: &[String]) -> Vec<LoadBalancerIngress> {
+    addresses.iter().map(|address| to_ingress(address)).collect()
+}
+
+fn to_ingress(address: &str) -> LoadBalancerIngress {
+    match address.parse::<IpAddr>() {
+        Ok(ip) => LoadBalancerIngress {
+            ip: Some(ip.to_string()),
+            ..LoadBalancerIngress::default()
+        },
+        Err(_) => LoadBalancerIngress {
+            hostname: Some(address.to_owned()),
+            ..LoadBalancerIngress::default()
+        },
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{Result, NodeBalancerError};
+use crate::admin::Readiness;
+use crate::config::ShutdownConfig;
+use crate::leader_election::LeaderElector;
+use crate::service_status::ServiceStatus;
+use crate::proxy::Proxy;
+use crate::router::Router;
+use std::time::Duration;
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// How long force-closed connections get to tear down before the process exits
+const CLOSE_GRACE: Duration = Duration::from_secs(1);
+
+// Longest clearing the service status and releasing the lease may take, so a hung API server can't hold up
+// draining past the pod's grace period
+const HANDOVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct ShutdownSignals {
+    terminate: Signal,
+    interrupt: Signal,
+}
+
+impl ShutdownSignals {
+    pub fn register() -> Result<ShutdownSignals> {
+        Ok(ShutdownSignals {
+            terminate: signal(SignalKind::terminate()).map_err(NodeBalancerError::IOError)?,
+            interrupt: signal(SignalKind::interrupt()).map_err(NodeBalancerError::IOError)?,
+        })
+    }
+
+    pub async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.terminate.recv() => "SIGTERM",
+            _ = self.interrupt.recv() => "SIGINT",
+        }
+    }
+}
+
+/// Stops the balancer in order: fail readiness and hand over leadership, stop accepting, let open connections
+/// finish up to the configured timeout, close whatever is left and stop the watchers. A second signal skips
+/// the waiting.
+pub async fn shutdown(config: &ShutdownConfig, signals: &mut ShutdownSignals, readiness: &Readiness, leader_election: &LeaderElector, service_status: &ServiceStatus, proxy: &Proxy, router: &Router) {
+    readiness.set_ready(false);
+
+    if !config.readiness_delay.is_zero() {
+        info!("Reporting not ready for {:?} before closing listeners", config.readiness_delay);
+    }
+
+    // Leadership is handed over while load balancers notice the balancer isn't ready
+    let handover = bounded_handover(async {
+        service_status.stop().await;
+        leader_election.release().await;
+    });
+
+    tokio::select! {
+        _ = async { tokio::join!(handover, tokio::time::sleep(config.readiness_delay)) } => {}
+        signal = signals.recv() => warn!("Got {} during shutdown, closing listeners now", signal),
+    }
+
+    proxy.stop_accepting().await;
+
+    let active = router.connections.active();
+    if active > 0 {
+        info!("Waiting up to {:?} for {} connections to finish", config.timeout, active);
+
+        tokio::select! {
+            finished = wait_idle(router, config.timeout) => {
+                if !finished {
+                    warn!("Shutdown timeout reached with {} connections open", router.connections.active());
+                }
+            }
+            signal = signals.recv() => warn!("Got {} during shutdown, closing connections now", signal),
+        }
+    }
+
+    let closed = router.connections.close_all();
+    if closed > 0 {
+        info!("Closing {} remaining connections", closed);
+        wait_idle(router, CLOSE_GRACE).await;
+    }
+
+    router.stop_watchers();
+    info!("Shutdown complete");
+}
+
+/// Gives up the lease when exiting before the balancer started serving
+pub async fn release_leadership(leader_election: &LeaderElector) {
+    bounded_handover(leader_election.release()).await;
+}
+
+async fn bounded_handover<F: std::future::Future<Output = ()>>(handover: F) {
+    if tokio::time::timeout(HANDOVER_TIMEOUT, handover).await.is_err() {
+        warn!("Handing over leadership took longer than {:?}, carrying on", HANDOVER_TIMEOUT);
+    }
+}
+
+// Returns whether every connection finished within the timeout
+async fn wait_idle(router: &Router, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+
+    while router.connections.active() > 0 {
+        if Instant::now() >= deadline {
+            return false;
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    true
+}